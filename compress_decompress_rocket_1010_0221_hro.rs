@@ -1,41 +1,1403 @@
+use rocket::data::{ByteUnit, Data, DataStream, Limits, ToByteUnit};
 use rocket::form::Form;
-use rocket::form::StringField;
-use rocket::response::status;
+use rocket::fs::{NamedFile, TempFile};
+use rocket::response::{self, status, Responder, Response};
 use rocket::serde::json::Json;
 use rocket::serde::json::serde_json::json;
 use rocket::serde::json::serde_json::Value;
-use rocket::http::Status;
-use flate2::read::GzDecoder;
+use rocket::http::{ContentType, Status};
+use rocket::serde::{Deserialize, Serialize};
+use rocket::request::{self, FromRequest, Request};
+use rocket::tokio;
+use rocket::tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncRead, AsyncReadExt, ReadBuf};
+use rocket::tokio::io::BufReader as AsyncBufReader;
+use async_compression::tokio::bufread as async_codec;
+use async_compression::Level;
+use flate2::read::{DeflateDecoder, GzDecoder, ZlibDecoder};
 use flate2::write::GzEncoder;
 use flate2::Compression;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::io;
 use std::io::prelude::*;
 use std::io::BufReader;
 use std::io::BufWriter;
 use std::fs::File;
+use std::path::{Component, Path, PathBuf};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{ready, Context, Poll};
+use std::time::{Duration, Instant};
 use rocket::post;
 use rocket::get;
 use rocket::Rocket;
+use rocket::State;
 use rocket::Config;
 
-// Define a structure to represent the request data
+#[path = "response_compression_fairing.rs"]
+mod response_compression;
+
+use response_compression::{compress_content, AcceptedCodecs, Codec, ResponseCompression};
+
+// Errors surfaced by the compression endpoints
+#[derive(Debug)]
+enum CompressError {
+    UnknownCodec(String),
+    InvalidLevel { codec: Codec, level: i32 },
+    UndetectedCodec,
+    InvalidBase64(base64::DecodeError),
+    PayloadTooLarge,
+    OutputTooLarge { limit: u64 },
+    RatioExceeded { limit: u64 },
+    DepthExceeded { limit: usize },
+    UnknownJob(String),
+    UnknownArchiveFormat(String),
+    UndetectedArchiveFormat,
+    UnsafeEntry(String),
+    JobNotFinished(String),
+    UnknownDictionary(u32),
+    MissingDictionaryId,
+    Io(io::Error),
+}
+
+impl CompressError {
+    fn status(&self) -> Status {
+        match self {
+            CompressError::UnknownCodec(_) | CompressError::InvalidLevel { .. } => Status::BadRequest,
+            CompressError::InvalidBase64(_) => Status::BadRequest,
+            CompressError::UndetectedCodec => Status::UnsupportedMediaType,
+            CompressError::PayloadTooLarge | CompressError::OutputTooLarge { .. } => Status::PayloadTooLarge,
+            CompressError::RatioExceeded { .. } | CompressError::DepthExceeded { .. } => Status::UnprocessableEntity,
+            CompressError::UnknownJob(_) => Status::NotFound,
+            CompressError::UnknownArchiveFormat(_) => Status::BadRequest,
+            CompressError::UndetectedArchiveFormat => Status::UnsupportedMediaType,
+            CompressError::UnsafeEntry(_) => Status::UnprocessableEntity,
+            CompressError::JobNotFinished(_) => Status::Conflict,
+            CompressError::UnknownDictionary(_) => Status::NotFound,
+            CompressError::MissingDictionaryId => Status::UnprocessableEntity,
+            CompressError::Io(_) => Status::UnprocessableEntity,
+        }
+    }
+
+    // The name of the decompression limit that tripped, if any
+    fn limit(&self) -> Option<&'static str> {
+        match self {
+            CompressError::OutputTooLarge { .. } => Some("max_output_bytes"),
+            CompressError::RatioExceeded { .. } => Some("max_ratio"),
+            CompressError::DepthExceeded { .. } => Some("max_depth"),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for CompressError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CompressError::UnknownCodec(name) => write!(f, "unknown codec: {}", name),
+            CompressError::InvalidLevel { codec, level } => {
+                let (min, max, _) = codec.levels();
+                write!(f, "level {} is outside {}..={} for {}", level, min, max, codec.name())
+            }
+            CompressError::UndetectedCodec => write!(f, "could not detect the codec, pass `codec` explicitly"),
+            CompressError::InvalidBase64(e) => write!(f, "invalid base64 data: {}", e),
+            CompressError::PayloadTooLarge => write!(f, "request body exceeds the size limit"),
+            CompressError::OutputTooLarge { limit } => write!(f, "decompressed output exceeds {} bytes", limit),
+            CompressError::RatioExceeded { limit } => write!(f, "expansion ratio exceeds {}:1", limit),
+            CompressError::DepthExceeded { limit } => write!(f, "more than {} nested compression layers", limit),
+            CompressError::UnknownJob(id) => write!(f, "no job with id {}", id),
+            CompressError::UnknownArchiveFormat(name) => write!(f, "unknown archive format: {}", name),
+            CompressError::UndetectedArchiveFormat => {
+                write!(f, "could not detect the archive format, pass `format` explicitly")
+            }
+            CompressError::UnsafeEntry(path) => write!(f, "unsafe archive entry: {}", path),
+            CompressError::JobNotFinished(id) => write!(f, "job {} has not completed successfully", id),
+            CompressError::UnknownDictionary(id) => write!(f, "no dictionary with id {}", id),
+            CompressError::MissingDictionaryId => write!(f, "the frame carries no dictionary id, pass `id` explicitly"),
+            CompressError::Io(e) => write!(f, "codec error: {}", e),
+        }
+    }
+}
+
+impl Error for CompressError {}
+
+impl From<io::Error> for CompressError {
+    fn from(err: io::Error) -> Self {
+        // Limits enforced inside a reader surface wrapped in an `io::Error`; unwrap them again
+        if err.get_ref().map_or(false, |inner| inner.is::<CompressError>()) {
+            let inner = err.into_inner().expect("checked above");
+            return *inner.downcast::<CompressError>().expect("checked above");
+        }
+        CompressError::Io(err)
+    }
+}
+
+impl From<zip::result::ZipError> for CompressError {
+    fn from(err: zip::result::ZipError) -> Self {
+        CompressError::Io(err.into())
+    }
+}
+
+impl<'r> Responder<'r, 'static> for CompressError {
+    fn respond_to(self, request: &'r Request<'_>) -> response::Result<'static> {
+        let status = self.status();
+        let body = match self.limit() {
+            Some(limit) => json!({ "error": self.to_string(), "limit": limit }),
+            None => json!({ "error": self.to_string() }),
+        };
+        status::Custom(status, Json(body)).respond_to(request)
+    }
+}
+
+// Caps applied to every decompression, read from the `decompress` table of the Rocket config
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(crate = "rocket::serde", default)]
+struct DecompressLimits {
+    // Largest output any single layer may produce
+    max_output_bytes: u64,
+    // Largest allowed output size relative to the compressed request body
+    max_ratio: u64,
+    // Most compression layers unwrapped when `nested` is requested
+    max_depth: usize,
+}
+
+impl Default for DecompressLimits {
+    fn default() -> Self {
+        DecompressLimits {
+            max_output_bytes: 64 * 1024 * 1024,
+            max_ratio: 100,
+            max_depth: 3,
+        }
+    }
+}
+
+impl DecompressLimits {
+    // The most output allowed when expanding a body of `origin_len` compressed bytes
+    fn output_cap(&self, origin_len: usize) -> u64 {
+        let ratio_cap = (origin_len.max(1) as u64).saturating_mul(self.max_ratio);
+        self.max_output_bytes.min(ratio_cap)
+    }
+
+    // The error reported once output grows past `cap`
+    fn exceeded(&self, cap: u64) -> CompressError {
+        if cap == self.max_output_bytes {
+            CompressError::OutputTooLarge { limit: self.max_output_bytes }
+        } else {
+            CompressError::RatioExceeded { limit: self.max_ratio }
+        }
+    }
+}
+
+// A raw compressed or decompressed body, tagged with the codecs that were used, outermost first
+struct CodecBody {
+    codecs: Vec<Codec>,
+    bytes: Vec<u8>,
+}
+
+fn codec_names(codecs: &[Codec]) -> String {
+    codecs.iter().map(|codec| codec.name()).collect::<Vec<_>>().join(", ")
+}
+
+impl<'r> Responder<'r, 'static> for CodecBody {
+    fn respond_to(self, request: &'r Request<'_>) -> response::Result<'static> {
+        Response::build_from((ContentType::Binary, self.bytes).respond_to(request)?)
+            .raw_header("X-Codec", codec_names(&self.codecs))
+            .ok()
+    }
+}
+
+// The JSON envelope for clients that cannot send binary bodies; `data` is base64
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+struct Envelope {
+    data: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    codec: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    level: Option<i32>,
+}
+
+// Pick the codec for a request: an explicit `codec` parameter wins over `Accept-Encoding`
+fn select_codec(codec: Option<&str>, accepted: &AcceptedCodecs) -> Result<Codec, CompressError> {
+    match codec {
+        Some(name) => Codec::from_name(name).ok_or_else(|| CompressError::UnknownCodec(name.to_string())),
+        None => Ok(accepted.preferred().unwrap_or(Codec::Gzip)),
+    }
+}
+
+// Pick the codec to decode with: an explicit `codec` parameter, otherwise the magic bytes
+fn decode_codec(codec: Option<&str>, input: &[u8]) -> Result<Codec, CompressError> {
+    match codec {
+        Some(name) => Codec::from_name(name).ok_or_else(|| CompressError::UnknownCodec(name.to_string())),
+        None => Codec::detect(input).ok_or(CompressError::UndetectedCodec),
+    }
+}
+
+fn resolve_level(codec: Codec, level: Option<i32>) -> Result<i32, CompressError> {
+    codec.level(level).ok_or(CompressError::InvalidLevel { codec, level: level.unwrap_or_default() })
+}
+
+// Read a whole request body, honouring the `compress` limit (`limits.compress` in the Rocket
+// config, 64 MiB by default). Rocket's own `bytes` limit is only 8 KiB, too small for real files.
+async fn read_body(body: Data<'_>, limits: &Limits) -> Result<Vec<u8>, CompressError> {
+    let limit = limits.get("compress").unwrap_or_else(|| 64.mebibytes());
+    let bytes = body.open(limit).into_bytes().await?;
+    if !bytes.is_complete() {
+        return Err(CompressError::PayloadTooLarge);
+    }
+    Ok(bytes.into_inner())
+}
+
+fn decode_base64(data: &str) -> Result<Vec<u8>, CompressError> {
+    BASE64.decode(data).map_err(CompressError::InvalidBase64)
+}
+
+#[post("/compress?<codec>&<level>", format = "application/octet-stream", data = "<body>")]
+async fn compress(
+    body: Data<'_>,
+    codec: Option<&str>,
+    level: Option<i32>,
+    accepted: AcceptedCodecs,
+    limits: &Limits,
+) -> Result<CodecBody, CompressError> {
+    let codec = select_codec(codec, &accepted)?;
+    let level = resolve_level(codec, level)?;
+    let input = read_body(body, limits).await?;
+    let bytes = compress_content(&input, codec, level)?;
+    Ok(CodecBody { codecs: vec![codec], bytes })
+}
+
+#[post("/compress", format = "json", data = "<envelope>", rank = 2)]
+fn compress_json(envelope: Json<Envelope>, accepted: AcceptedCodecs) -> Result<Json<Envelope>, CompressError> {
+    let envelope = envelope.into_inner();
+    let codec = select_codec(envelope.codec.as_deref(), &accepted)?;
+    let level = resolve_level(codec, envelope.level)?;
+    let input = decode_base64(&envelope.data)?;
+    let compressed = compress_content(&input, codec, level)?;
+    Ok(Json(Envelope {
+        data: BASE64.encode(compressed),
+        codec: Some(codec.name().to_string()),
+        level: Some(level),
+    }))
+}
+
+#[post("/decompress?<codec>&<nested>", format = "application/octet-stream", data = "<body>")]
+async fn decompress(
+    body: Data<'_>,
+    codec: Option<&str>,
+    nested: Option<bool>,
+    limits: &Limits,
+    decompress_limits: &State<DecompressLimits>,
+) -> Result<CodecBody, CompressError> {
+    let input = read_body(body, limits).await?;
+    let codec = decode_codec(codec, &input)?;
+    let (codecs, bytes) = decompress_layers(&input, codec, nested.unwrap_or(false), decompress_limits)?;
+    Ok(CodecBody { codecs, bytes })
+}
+
+#[post("/decompress?<nested>", format = "json", data = "<envelope>", rank = 2)]
+fn decompress_json(
+    envelope: Json<Envelope>,
+    nested: Option<bool>,
+    decompress_limits: &State<DecompressLimits>,
+) -> Result<Json<Envelope>, CompressError> {
+    let envelope = envelope.into_inner();
+    let input = decode_base64(&envelope.data)?;
+    let codec = decode_codec(envelope.codec.as_deref(), &input)?;
+    let (codecs, decompressed) = decompress_layers(&input, codec, nested.unwrap_or(false), decompress_limits)?;
+    Ok(Json(Envelope {
+        data: BASE64.encode(decompressed),
+        codec: Some(codec_names(&codecs)),
+        level: None,
+    }))
+}
+
+// One row of the benchmark table
+#[derive(Debug, Serialize)]
+#[serde(crate = "rocket::serde")]
+struct BenchmarkRow {
+    codec: &'static str,
+    level: i32,
+    input_bytes: usize,
+    compressed_bytes: usize,
+    ratio: f64,
+    compress_mb_per_s: f64,
+    decompress_mb_per_s: f64,
+}
+
+fn throughput(bytes: usize, elapsed: Duration) -> f64 {
+    bytes as f64 / (1024.0 * 1024.0) / elapsed.as_secs_f64().max(f64::EPSILON)
+}
+
+// Compress and decompress the sample once per codec and level
+fn run_benchmark(sample: &[u8], codecs: &[Codec]) -> Result<Vec<BenchmarkRow>, CompressError> {
+    // The sample is trusted and its size known, so only the exact output size is enforced
+    let limits = DecompressLimits { max_output_bytes: sample.len() as u64, max_ratio: u64::MAX, max_depth: 1 };
+    let mut rows = Vec::new();
+    for &codec in codecs {
+        let (min, max, _) = codec.levels();
+        for level in min..=max {
+            let started = Instant::now();
+            let compressed = compress_content(sample, codec, level)?;
+            let compress_time = started.elapsed();
+
+            let started = Instant::now();
+            decompress_content(&compressed, codec, &limits, compressed.len())?;
+            let decompress_time = started.elapsed();
+
+            rows.push(BenchmarkRow {
+                codec: codec.name(),
+                level,
+                input_bytes: sample.len(),
+                compressed_bytes: compressed.len(),
+                ratio: sample.len() as f64 / compressed.len().max(1) as f64,
+                compress_mb_per_s: throughput(sample.len(), compress_time),
+                decompress_mb_per_s: throughput(sample.len(), decompress_time),
+            });
+        }
+    }
+    Ok(rows)
+}
+
+#[post("/compress/benchmark?<codec>", format = "application/octet-stream", data = "<body>")]
+async fn benchmark(
+    body: Data<'_>,
+    codec: Option<&str>,
+    limits: &Limits,
+) -> Result<Json<Vec<BenchmarkRow>>, CompressError> {
+    let codecs = match codec {
+        Some(name) => vec![Codec::from_name(name).ok_or_else(|| CompressError::UnknownCodec(name.to_string()))?],
+        None => Codec::ALL.to_vec(),
+    };
+    let sample = read_body(body, limits).await?;
+    // Every level of every codec is CPU-bound work, so keep it off the async workers
+    let rows = tokio::task::spawn_blocking(move || run_benchmark(&sample, &codecs))
+        .await
+        .map_err(|e| CompressError::Io(io::Error::new(io::ErrorKind::Other, e)))??;
+    Ok(Json(rows))
+}
+
+// Trained zstd dictionaries, keyed by the dictionary id zstd writes into every frame.
+// Each dictionary is persisted as `<id>.dict` under `dir` and reloaded at startup.
+struct DictionaryStore {
+    dir: PathBuf,
+    dictionaries: Mutex<HashMap<u32, Arc<Vec<u8>>>>,
+}
+
+impl DictionaryStore {
+    fn load(dir: PathBuf) -> io::Result<Self> {
+        let mut dictionaries = HashMap::new();
+        for entry in std::fs::read_dir(&dir)? {
+            let path = entry?.path();
+            if path.extension().map_or(true, |ext| ext != "dict") {
+                continue;
+            }
+            let dictionary = std::fs::read(&path)?;
+            if let Some(id) = zstd::zstd_safe::get_dict_id_from_dict(&dictionary) {
+                dictionaries.insert(id.get(), Arc::new(dictionary));
+            }
+        }
+        Ok(DictionaryStore { dir, dictionaries: Mutex::new(dictionaries) })
+    }
+
+    fn insert(&self, dictionary: Vec<u8>) -> Result<u32, CompressError> {
+        let id = zstd::zstd_safe::get_dict_id_from_dict(&dictionary)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "trained dictionary has no id"))?
+            .get();
+        std::fs::write(self.dir.join(format!("{}.dict", id)), &dictionary)?;
+        self.dictionaries.lock().unwrap().insert(id, Arc::new(dictionary));
+        Ok(id)
+    }
+
+    fn get(&self, id: u32) -> Result<Arc<Vec<u8>>, CompressError> {
+        self.dictionaries.lock().unwrap().get(&id).cloned().ok_or(CompressError::UnknownDictionary(id))
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(crate = "rocket::serde")]
+struct TrainRequest {
+    // Base64-encoded sample documents
+    samples: Vec<String>,
+    #[serde(default = "default_dictionary_size")]
+    max_size: usize,
+}
+
+fn default_dictionary_size() -> usize {
+    112_640
+}
+
+#[derive(Debug, Serialize)]
+#[serde(crate = "rocket::serde")]
+struct DictionaryInfo {
+    id: u32,
+    size: usize,
+}
+
+#[post("/dictionaries", format = "json", data = "<request>")]
+async fn train_dictionary(
+    request: Json<TrainRequest>,
+    store: &State<DictionaryStore>,
+) -> Result<status::Created<Json<DictionaryInfo>>, CompressError> {
+    let samples = request.samples.iter().map(|sample| decode_base64(sample)).collect::<Result<Vec<_>, _>>()?;
+    let max_size = request.max_size;
+    // Training scans the whole corpus, so keep it off the async workers
+    let dictionary = tokio::task::spawn_blocking(move || zstd::dict::from_samples(&samples, max_size))
+        .await
+        .map_err(|e| CompressError::Io(io::Error::new(io::ErrorKind::Other, e)))??;
+    let size = dictionary.len();
+    let id = store.insert(dictionary)?;
+    Ok(status::Created::new(format!("/dictionaries/{}", id)).body(Json(DictionaryInfo { id, size })))
+}
+
+#[get("/dictionaries")]
+fn list_dictionaries(store: &State<DictionaryStore>) -> Json<Vec<DictionaryInfo>> {
+    let dictionaries = store.dictionaries.lock().unwrap();
+    let mut infos: Vec<DictionaryInfo> =
+        dictionaries.iter().map(|(&id, dictionary)| DictionaryInfo { id, size: dictionary.len() }).collect();
+    infos.sort_by_key(|info| info.id);
+    Json(infos)
+}
+
+#[post("/compress/dict/<id>?<level>", format = "application/octet-stream", data = "<body>")]
+async fn compress_with_dictionary(
+    id: u32,
+    body: Data<'_>,
+    level: Option<i32>,
+    limits: &Limits,
+    store: &State<DictionaryStore>,
+) -> Result<CodecBody, CompressError> {
+    let level = resolve_level(Codec::Zstd, level)?;
+    let dictionary = store.get(id)?;
+    let input = read_body(body, limits).await?;
+    // The dictionary id is written into the frame header so decompression can find it again
+    let mut compressor = zstd::bulk::Compressor::with_dictionary(level, &dictionary)?;
+    compressor.include_dictid(true)?;
+    let bytes = compressor.compress(&input)?;
+    Ok(CodecBody { codecs: vec![Codec::Zstd], bytes })
+}
+
+#[post("/decompress/dict?<id>", format = "application/octet-stream", data = "<body>")]
+async fn decompress_with_dictionary(
+    body: Data<'_>,
+    id: Option<u32>,
+    limits: &Limits,
+    store: &State<DictionaryStore>,
+    decompress_limits: &State<DecompressLimits>,
+) -> Result<CodecBody, CompressError> {
+    let input = read_body(body, limits).await?;
+    let id = match id {
+        Some(id) => id,
+        None => zstd::zstd_safe::get_dict_id_from_frame(&input).ok_or(CompressError::MissingDictionaryId)?.get(),
+    };
+    let dictionary = store.get(id)?;
+    let decoder = zstd::stream::read::Decoder::with_dictionary(&input[..], &dictionary)?;
+    let bytes = read_limited(decoder, decompress_limits, input.len())?;
+    Ok(CodecBody { codecs: vec![Codec::Zstd], bytes })
+}
+
+// Counts the bytes pulled through an async reader, so progress and ratios can be tracked
+struct CountingReader<R> {
+    inner: R,
+    count: Arc<AtomicU64>,
+}
+
+impl<R> CountingReader<R> {
+    fn new(inner: R, count: Arc<AtomicU64>) -> Self {
+        CountingReader { inner, count }
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for CountingReader<R> {
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        let before = buf.filled().len();
+        ready!(Pin::new(&mut self.inner).poll_read(cx, buf))?;
+        let read = (buf.filled().len() - before) as u64;
+        self.count.fetch_add(read, Ordering::Relaxed);
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<R: Read> Read for CountingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.count.fetch_add(read as u64, Ordering::Relaxed);
+        Ok(read)
+    }
+}
+
+// Fails a decoding stream as soon as its output crosses the decompression limits.
+// `consumed` counts the compressed bytes read so far and is the base for the ratio check.
+struct LimitedReader<R> {
+    inner: R,
+    produced: u64,
+    consumed: Arc<AtomicU64>,
+    limits: DecompressLimits,
+}
+
+impl<R> LimitedReader<R> {
+    fn new(inner: R, consumed: Arc<AtomicU64>, limits: DecompressLimits) -> Self {
+        LimitedReader { inner, produced: 0, consumed, limits }
+    }
+
+    // Account for `read` more bytes of output and fail if a limit is now exceeded
+    fn produce(&mut self, read: usize) -> io::Result<()> {
+        self.produced += read as u64;
+        let consumed = self.consumed.load(Ordering::Relaxed).max(1);
+        let error = if self.produced > self.limits.max_output_bytes {
+            CompressError::OutputTooLarge { limit: self.limits.max_output_bytes }
+        } else if self.produced > consumed.saturating_mul(self.limits.max_ratio) {
+            CompressError::RatioExceeded { limit: self.limits.max_ratio }
+        } else {
+            return Ok(());
+        };
+        Err(io::Error::new(io::ErrorKind::InvalidData, error))
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for LimitedReader<R> {
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        let before = buf.filled().len();
+        ready!(Pin::new(&mut self.inner).poll_read(cx, buf))?;
+        let read = buf.filled().len() - before;
+        Poll::Ready(self.produce(read))
+    }
+}
+
+impl<R: Read> Read for LimitedReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.produce(read)?;
+        Ok(read)
+    }
+}
+
+type BoxedReader<'a> = Pin<Box<dyn AsyncRead + Send + 'a>>;
+
+// Wrap a buffered reader in a streaming encoder for the codec
+fn encode_stream<'a, R: AsyncBufRead + Send + 'a>(reader: R, codec: Codec, level: i32) -> BoxedReader<'a> {
+    let level = Level::Precise(level);
+    match codec {
+        Codec::Gzip => Box::pin(async_codec::GzipEncoder::with_quality(reader, level)),
+        Codec::Zlib => Box::pin(async_codec::ZlibEncoder::with_quality(reader, level)),
+        Codec::Deflate => Box::pin(async_codec::DeflateEncoder::with_quality(reader, level)),
+        Codec::Zstd => Box::pin(async_codec::ZstdEncoder::with_quality(reader, level)),
+        Codec::Brotli => Box::pin(async_codec::BrotliEncoder::with_quality(reader, level)),
+    }
+}
+
+// Wrap a buffered reader in a streaming decoder for the codec
+fn decode_stream<'a, R: AsyncBufRead + Send + 'a>(reader: R, codec: Codec) -> BoxedReader<'a> {
+    match codec {
+        Codec::Gzip => Box::pin(async_codec::GzipDecoder::new(reader)),
+        Codec::Zlib => Box::pin(async_codec::ZlibDecoder::new(reader)),
+        Codec::Deflate => Box::pin(async_codec::DeflateDecoder::new(reader)),
+        Codec::Zstd => Box::pin(async_codec::ZstdDecoder::new(reader)),
+        Codec::Brotli => Box::pin(async_codec::BrotliDecoder::new(reader)),
+    }
+}
+
+// Like `decode_codec`, but peeks at the head of a stream without consuming it
+async fn sniff_codec<R: AsyncBufRead + Unpin>(reader: &mut R, codec: Option<&str>) -> Result<Codec, CompressError> {
+    let head = match codec {
+        Some(_) => &[][..],
+        None => reader.fill_buf().await?,
+    };
+    decode_codec(codec, head)
+}
+
+// The upload cap for streaming endpoints, configurable as the `stream` limit
+fn stream_limit(limits: &Limits) -> ByteUnit {
+    limits.get("stream").unwrap_or(16.gibibytes())
+}
+
+// The `Content-Length` a client declared, so a streamed upload that is known to be too large
+// can be refused before any of the response has been sent
+struct DeclaredLength(Option<u64>);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for DeclaredLength {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        let length = request.headers().get_one("Content-Length").and_then(|value| value.trim().parse().ok());
+        request::Outcome::Success(DeclaredLength(length))
+    }
+}
+
+// A streamed upload that fails once it goes past its limit. Rocket's `DataStream` simply ends
+// at its cap, which would pass a truncated body off as a complete one, so the stream is opened
+// one byte beyond the limit and reaching that byte is an error.
+struct UploadStream<'r> {
+    stream: DataStream<'r>,
+    remaining: u64,
+}
+
+impl<'r> UploadStream<'r> {
+    fn open(body: Data<'r>, declared: DeclaredLength, limit: ByteUnit) -> Result<Self, CompressError> {
+        if declared.0.map_or(false, |length| length > limit.as_u64()) {
+            return Err(CompressError::PayloadTooLarge);
+        }
+        Ok(UploadStream { stream: body.open(limit + 1), remaining: limit.as_u64() })
+    }
+}
+
+impl AsyncRead for UploadStream<'_> {
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        let before = buf.filled().len();
+        ready!(Pin::new(&mut self.stream).poll_read(cx, buf))?;
+        let read = (buf.filled().len() - before) as u64;
+        if read > self.remaining {
+            return Poll::Ready(Err(io::Error::new(io::ErrorKind::InvalidData, CompressError::PayloadTooLarge)));
+        }
+        self.remaining -= read;
+        Poll::Ready(Ok(()))
+    }
+}
+
+// A streamed response body, tagged with the codec that was used
+struct CodecStream<'r> {
+    codec: Codec,
+    reader: BoxedReader<'r>,
+}
+
+impl<'r> Responder<'r, 'r> for CodecStream<'r> {
+    fn respond_to(self, _: &'r Request<'_>) -> response::Result<'r> {
+        Response::build()
+            .header(ContentType::Binary)
+            .raw_header("X-Codec", self.codec.name())
+            .streamed_body(self.reader)
+            .ok()
+    }
+}
+
+#[post("/compress/stream?<codec>&<level>", format = "application/octet-stream", data = "<body>")]
+async fn compress_stream<'r>(
+    body: Data<'r>,
+    codec: Option<&str>,
+    level: Option<i32>,
+    accepted: AcceptedCodecs,
+    declared: DeclaredLength,
+    limits: &Limits,
+) -> Result<CodecStream<'r>, CompressError> {
+    let codec = select_codec(codec, &accepted)?;
+    let level = resolve_level(codec, level)?;
+    let reader = AsyncBufReader::new(UploadStream::open(body, declared, stream_limit(limits))?);
+    Ok(CodecStream { codec, reader: encode_stream(reader, codec, level) })
+}
+
+#[post("/decompress/stream?<codec>", format = "application/octet-stream", data = "<body>")]
+async fn decompress_stream<'r>(
+    body: Data<'r>,
+    codec: Option<&str>,
+    declared: DeclaredLength,
+    limits: &Limits,
+    decompress_limits: &State<DecompressLimits>,
+) -> Result<CodecStream<'r>, CompressError> {
+    let upload = UploadStream::open(body, declared, stream_limit(limits))?;
+    let consumed = Arc::new(AtomicU64::new(0));
+    let mut reader = AsyncBufReader::new(CountingReader::new(upload, consumed.clone()));
+    let codec = sniff_codec(&mut reader, codec).await?;
+    let decoded = LimitedReader::new(decode_stream(reader, codec), consumed, *decompress_limits.inner());
+    Ok(CodecStream { codec, reader: prefetch(Box::pin(decoded)).await? })
+}
+
+// How much decoded output a streamed response buffers before its status is sent
+const STREAM_PREFETCH: u64 = 1024 * 1024;
+
+// Decode the head of a stream up front, so that corrupt input and the typical decompression
+// bomb fail with a proper status and error body. Once streaming has started, a failure can
+// only abort the response.
+async fn prefetch(mut reader: BoxedReader<'_>) -> Result<BoxedReader<'_>, CompressError> {
+    let mut head = Vec::new();
+    (&mut reader).take(STREAM_PREFETCH).read_to_end(&mut head).await?;
+    Ok(Box::pin(AsyncReadExt::chain(io::Cursor::new(head), reader)))
+}
+
+// What a background job does with its input
+#[derive(Debug, Clone, Copy)]
+enum Operation {
+    Compress { level: i32 },
+    Decompress,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(crate = "rocket::serde", rename_all = "snake_case")]
+enum JobState {
+    Running,
+    Done,
+    Failed,
+}
+
+struct Job {
+    operation: Operation,
+    codec: Codec,
+    total_bytes: u64,
+    bytes_in: Arc<AtomicU64>,
+    bytes_out: u64,
+    state: JobState,
+    error: Option<String>,
+    limit: Option<&'static str>,
+    output: PathBuf,
+    finished: Option<Instant>,
+}
+
+// The progress report returned by the job endpoints
+#[derive(Debug, Serialize)]
+#[serde(crate = "rocket::serde")]
+struct JobStatus {
+    id: String,
+    operation: &'static str,
+    codec: &'static str,
+    state: JobState,
+    bytes_in: u64,
+    total_bytes: u64,
+    bytes_out: u64,
+    progress: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    // The decompression limit that failed the job, as in the buffered endpoints' errors
+    #[serde(skip_serializing_if = "Option::is_none")]
+    limit: Option<&'static str>,
+}
+
+impl Job {
+    fn status(&self, id: uuid::Uuid) -> JobStatus {
+        let bytes_in = self.bytes_in.load(Ordering::Relaxed);
+        let progress = match self.state {
+            JobState::Done => 1.0,
+            _ if self.total_bytes == 0 => 0.0,
+            _ => bytes_in as f64 / self.total_bytes as f64,
+        };
+        JobStatus {
+            id: id.to_string(),
+            operation: match self.operation {
+                Operation::Compress { .. } => "compress",
+                Operation::Decompress => "decompress",
+            },
+            codec: self.codec.name(),
+            state: self.state,
+            bytes_in,
+            total_bytes: self.total_bytes,
+            bytes_out: self.bytes_out,
+            progress,
+            error: self.error.clone(),
+            limit: self.limit,
+        }
+    }
+}
+
+// Background jobs for inputs too large to hold a request open for.
+// Uploads are spooled to `dir`, processed by a spawned task and kept there for download
+// until `ttl` after they finish. Ids are random so one client cannot walk another's results.
+struct JobRegistry {
+    dir: PathBuf,
+    ttl: Duration,
+    jobs: Arc<Mutex<HashMap<uuid::Uuid, Job>>>,
+}
+
+impl JobRegistry {
+    // Jobs live only in memory, so files left by a previous run can never be fetched again
+    fn open(dir: PathBuf, ttl: Duration) -> io::Result<Self> {
+        for entry in std::fs::read_dir(&dir)? {
+            let path = entry?.path();
+            let spooled = path.extension().map_or(false, |ext| ext == "in" || ext == "out");
+            let stem = path.file_stem().and_then(|stem| stem.to_str()).unwrap_or_default();
+            if spooled && uuid::Uuid::parse_str(stem).is_ok() {
+                std::fs::remove_file(&path)?;
+            }
+        }
+        Ok(JobRegistry { dir, ttl, jobs: Arc::new(Mutex::new(HashMap::new())) })
+    }
+
+    // Forget finished jobs older than the ttl and delete their output files
+    fn prune(&self) {
+        let mut jobs = self.jobs.lock().unwrap();
+        let expired: Vec<uuid::Uuid> = jobs
+            .iter()
+            .filter(|(_, job)| job.finished.map_or(false, |finished| finished.elapsed() > self.ttl))
+            .map(|(&id, _)| id)
+            .collect();
+        for id in expired {
+            if let Some(job) = jobs.remove(&id) {
+                let _ = std::fs::remove_file(&job.output);
+            }
+        }
+    }
+
+    // Spool the upload to disk and start processing it in the background
+    async fn submit(
+        &self,
+        body: Data<'_>,
+        limit: ByteUnit,
+        operation: Operation,
+        codec: Option<Codec>,
+        decompress_limits: DecompressLimits,
+    ) -> Result<JobStatus, CompressError> {
+        self.prune();
+        let id = uuid::Uuid::new_v4();
+        let input = self.dir.join(format!("{}.in", id.simple()));
+        let output = self.dir.join(format!("{}.out", id.simple()));
+        let bytes_in = Arc::new(AtomicU64::new(0));
+
+        // Until the job is spawned, any failure must not leave the spooled upload behind
+        let started = async {
+            let spooled = body.open(limit).into_file(&input).await?;
+            if !spooled.is_complete() {
+                return Err(CompressError::PayloadTooLarge);
+            }
+            let total_bytes = spooled.n.written;
+            let codec = match codec {
+                Some(codec) => codec,
+                None => {
+                    let mut reader = AsyncBufReader::new(tokio::fs::File::open(&input).await?);
+                    Codec::detect(reader.fill_buf().await?).ok_or(CompressError::UndetectedCodec)?
+                }
+            };
+            Ok::<_, CompressError>((total_bytes, codec))
+        }
+        .await;
+        let (total_bytes, codec) = match started {
+            Ok(started) => started,
+            Err(e) => {
+                let _ = tokio::fs::remove_file(&input).await;
+                return Err(e);
+            }
+        };
+
+        let job = Job {
+            operation,
+            codec,
+            total_bytes,
+            bytes_in: bytes_in.clone(),
+            bytes_out: 0,
+            state: JobState::Running,
+            error: None,
+            limit: None,
+            output: output.clone(),
+            finished: None,
+        };
+        let status = job.status(id);
+        self.jobs.lock().unwrap().insert(id, job);
+
+        let jobs = self.jobs.clone();
+        tokio::task::spawn_blocking(move || {
+            let result = run_job(&input, &output, operation, codec, bytes_in, decompress_limits);
+            let _ = std::fs::remove_file(&input);
+            if let Some(job) = jobs.lock().unwrap().get_mut(&id) {
+                job.finished = Some(Instant::now());
+                match result {
+                    Ok(written) => {
+                        job.bytes_out = written;
+                        job.state = JobState::Done;
+                    }
+                    Err(e) => {
+                        let e = CompressError::from(e);
+                        job.state = JobState::Failed;
+                        job.error = Some(e.to_string());
+                        job.limit = e.limit();
+                    }
+                }
+            }
+        });
+        Ok(status)
+    }
+
+    fn status(&self, id: &str) -> Result<JobStatus, CompressError> {
+        self.prune();
+        let jobs = self.jobs.lock().unwrap();
+        let (&key, job) = Self::find(&jobs, id)?;
+        Ok(job.status(key))
+    }
+
+    fn result_path(&self, id: &str) -> Result<PathBuf, CompressError> {
+        self.prune();
+        let jobs = self.jobs.lock().unwrap();
+        let (_, job) = Self::find(&jobs, id)?;
+        match job.state {
+            JobState::Done => Ok(job.output.clone()),
+            _ => Err(CompressError::JobNotFinished(id.to_string())),
+        }
+    }
+
+    fn find<'a>(jobs: &'a HashMap<uuid::Uuid, Job>, id: &str) -> Result<(&'a uuid::Uuid, &'a Job), CompressError> {
+        uuid::Uuid::parse_str(id)
+            .ok()
+            .and_then(|key| jobs.get_key_value(&key))
+            .ok_or_else(|| CompressError::UnknownJob(id.to_string()))
+    }
+}
+
+// Run a job's codec over its spooled input, returning the number of bytes written.
+// flate2, zstd and brotli all block, so this is called on the blocking thread pool.
+fn run_job(
+    input: &Path,
+    output: &Path,
+    operation: Operation,
+    codec: Codec,
+    bytes_in: Arc<AtomicU64>,
+    limits: DecompressLimits,
+) -> io::Result<u64> {
+    let reader = CountingReader::new(File::open(input)?, bytes_in.clone());
+    let mut stream: Box<dyn Read> = match operation {
+        Operation::Compress { level } => encode_reader(reader, codec, level)?,
+        Operation::Decompress => Box::new(LimitedReader::new(decode_reader(reader, codec)?, bytes_in, limits)),
+    };
+    let written = File::create(output).and_then(|file| {
+        let mut writer = BufWriter::new(file);
+        let written = io::copy(&mut stream, &mut writer)?;
+        writer.flush()?;
+        Ok(written)
+    });
+    if written.is_err() {
+        let _ = std::fs::remove_file(output);
+    }
+    written
+}
+
+// Wrap a blocking reader in an encoder for the codec
+fn encode_reader<'a, R: Read + 'a>(reader: R, codec: Codec, level: i32) -> io::Result<Box<dyn Read + 'a>> {
+    let compression = Compression::new(level as u32);
+    Ok(match codec {
+        Codec::Gzip => Box::new(flate2::read::GzEncoder::new(reader, compression)),
+        Codec::Zlib => Box::new(flate2::read::ZlibEncoder::new(reader, compression)),
+        Codec::Deflate => Box::new(flate2::read::DeflateEncoder::new(reader, compression)),
+        Codec::Zstd => Box::new(zstd::stream::read::Encoder::new(reader, level)?),
+        Codec::Brotli => Box::new(brotli::CompressorReader::new(reader, 4096, level as u32, 22)),
+    })
+}
+
+// Wrap a blocking reader in a decoder for the codec
+fn decode_reader<'a, R: Read + 'a>(reader: R, codec: Codec) -> io::Result<Box<dyn Read + 'a>> {
+    Ok(match codec {
+        Codec::Gzip => Box::new(GzDecoder::new(reader)),
+        Codec::Zlib => Box::new(ZlibDecoder::new(reader)),
+        Codec::Deflate => Box::new(DeflateDecoder::new(reader)),
+        Codec::Zstd => Box::new(zstd::stream::read::Decoder::new(reader)?),
+        Codec::Brotli => Box::new(brotli::Decompressor::new(reader, 4096)),
+    })
+}
+
+#[post("/jobs/compress?<codec>&<level>", format = "application/octet-stream", data = "<body>")]
+async fn compress_job(
+    body: Data<'_>,
+    codec: Option<&str>,
+    level: Option<i32>,
+    accepted: AcceptedCodecs,
+    limits: &Limits,
+    jobs: &State<JobRegistry>,
+    decompress_limits: &State<DecompressLimits>,
+) -> Result<status::Accepted<Json<JobStatus>>, CompressError> {
+    let codec = select_codec(codec, &accepted)?;
+    let level = resolve_level(codec, level)?;
+    let operation = Operation::Compress { level };
+    let status = jobs.submit(body, stream_limit(limits), operation, Some(codec), *decompress_limits.inner()).await?;
+    Ok(status::Accepted(Some(Json(status))))
+}
+
+#[post("/jobs/decompress?<codec>", format = "application/octet-stream", data = "<body>")]
+async fn decompress_job(
+    body: Data<'_>,
+    codec: Option<&str>,
+    limits: &Limits,
+    jobs: &State<JobRegistry>,
+    decompress_limits: &State<DecompressLimits>,
+) -> Result<status::Accepted<Json<JobStatus>>, CompressError> {
+    let codec = match codec {
+        Some(name) => Some(Codec::from_name(name).ok_or_else(|| CompressError::UnknownCodec(name.to_string()))?),
+        None => None,
+    };
+    let status = jobs.submit(body, stream_limit(limits), Operation::Decompress, codec, *decompress_limits.inner()).await?;
+    Ok(status::Accepted(Some(Json(status))))
+}
+
+#[get("/jobs/<id>")]
+fn job_status(id: &str, jobs: &State<JobRegistry>) -> Result<Json<JobStatus>, CompressError> {
+    jobs.status(id).map(Json)
+}
+
+#[get("/jobs/<id>/result")]
+async fn job_result(id: &str, jobs: &State<JobRegistry>) -> Result<(ContentType, NamedFile), CompressError> {
+    let path = jobs.result_path(id)?;
+    Ok((ContentType::Binary, NamedFile::open(path).await?))
+}
+
+// The archive formats the bundling endpoints can build and unpack
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ArchiveFormat {
+    Tar,
+    TarGz,
+    Zip,
+}
+
+impl ArchiveFormat {
+    fn from_name(name: &str) -> Option<ArchiveFormat> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "tar" => Some(ArchiveFormat::Tar),
+            "tar.gz" | "tgz" => Some(ArchiveFormat::TarGz),
+            "zip" => Some(ArchiveFormat::Zip),
+            _ => None,
+        }
+    }
+
+    fn content_type(self) -> ContentType {
+        match self {
+            ArchiveFormat::Tar => ContentType::new("application", "x-tar"),
+            ArchiveFormat::TarGz => ContentType::GZIP,
+            ArchiveFormat::Zip => ContentType::ZIP,
+        }
+    }
+
+    // Guess the format from magic bytes; a gzip stream is assumed to wrap a tarball
+    fn detect(input: &[u8]) -> Option<ArchiveFormat> {
+        match input {
+            [b'P', b'K', 0x03, 0x04, ..] => Some(ArchiveFormat::Zip),
+            [0x1f, 0x8b, ..] => Some(ArchiveFormat::TarGz),
+            _ if input.get(257..262) == Some(&b"ustar"[..]) => Some(ArchiveFormat::Tar),
+            _ => None,
+        }
+    }
+}
+
+// Pick the archive format: an explicit `format` parameter, otherwise the magic bytes
+fn archive_format(format: Option<&str>, input: &[u8]) -> Result<ArchiveFormat, CompressError> {
+    match format {
+        Some(name) => ArchiveFormat::from_name(name).ok_or_else(|| CompressError::UnknownArchiveFormat(name.to_string())),
+        None => ArchiveFormat::detect(input).ok_or(CompressError::UndetectedArchiveFormat),
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(crate = "rocket::serde")]
+struct ArchiveEntry {
+    path: String,
+    size: u64,
+    kind: &'static str,
+}
+
 #[derive(FromForm)]
-struct CompressRequest {
-    #[field(value = "input")]
-    content: String,
+struct ArchiveUpload<'r> {
+    files: Vec<TempFile<'r>>,
+}
+
+// Turn an entry name into a relative path, rejecting absolute paths and `..` components
+fn safe_entry_path(name: &Path) -> Result<PathBuf, CompressError> {
+    let mut path = PathBuf::new();
+    for component in name.components() {
+        match component {
+            Component::Normal(part) => path.push(part),
+            Component::CurDir => {}
+            _ => return Err(CompressError::UnsafeEntry(name.display().to_string())),
+        }
+    }
+    if path.as_os_str().is_empty() {
+        return Err(CompressError::UnsafeEntry(name.display().to_string()));
+    }
+    Ok(path)
 }
 
-#[post("/compress", data = "<compress_request>")]
-fn compress(compress_request: Form<CompressRequest>) -> Result<status::Accepted<Json<String>>, Status> {
-    let content = compress_request.into_inner().content;
-    let compressed = compress_content(content.as_bytes());
-    Ok(status::Accepted(Some(json!{"compressed": String::from_utf8(compressed).expect("Failed to convert compressed data to UTF-8")})))
+// Only allow symlinks that point down from their own directory. A `..` cannot be checked
+// lexically, because earlier links in the same archive change where it resolves to;
+// without one, any chain of links stays below the directory of the first link.
+fn check_link(entry: &Path, target: &Path) -> Result<(), CompressError> {
+    let safe = target.components().all(|component| matches!(component, Component::Normal(_) | Component::CurDir));
+    if !safe || target.as_os_str().is_empty() {
+        return Err(CompressError::UnsafeEntry(format!("{} -> {}", entry.display(), target.display())));
+    }
+    Ok(())
 }
 
-#[post("/decompress", data = "<compress_request>")]
-fn decompress(compress_request: Form<CompressRequest>) -> Result<status::Accepted<Json<String>>, Status> {
-    let content = compress_request.into_inner().content;
-    let decompressed = decompress_content(content.as_bytes());
-    Ok(status::Accepted(Some(json!{"decompressed": String::from_utf8(decompressed).expect("Failed to convert decompressed data to UTF-8")})))
+// Resolve where an entry lands under `dest`, refusing to write through an already extracted symlink
+fn prepare_target(dest: &Path, relative: &Path) -> Result<PathBuf, CompressError> {
+    let mut target = dest.to_path_buf();
+    for component in relative.components() {
+        target.push(component);
+        if std::fs::symlink_metadata(&target).map(|m| m.file_type().is_symlink()).unwrap_or(false) {
+            return Err(CompressError::UnsafeEntry(relative.display().to_string()));
+        }
+    }
+    if let Some(parent) = target.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    Ok(target)
+}
+
+// Tracks the bytes written during an extraction against the decompression limits
+struct OutputBudget<'a> {
+    limits: &'a DecompressLimits,
+    cap: u64,
+    written: u64,
+}
+
+impl<'a> OutputBudget<'a> {
+    fn new(limits: &'a DecompressLimits, origin_len: usize) -> Self {
+        OutputBudget { limits, cap: limits.output_cap(origin_len), written: 0 }
+    }
+
+    fn copy<R: Read, W: Write>(&mut self, reader: R, writer: &mut W) -> Result<u64, CompressError> {
+        let remaining = self.cap - self.written;
+        let copied = io::copy(&mut reader.take(remaining.saturating_add(1)), writer)?;
+        if copied > remaining {
+            return Err(self.limits.exceeded(self.cap));
+        }
+        self.written += copied;
+        Ok(copied)
+    }
+}
+
+fn tar_kind(entry_type: tar::EntryType) -> &'static str {
+    match entry_type {
+        tar::EntryType::Directory => "dir",
+        tar::EntryType::Symlink => "symlink",
+        tar::EntryType::Link => "hardlink",
+        tar::EntryType::Regular | tar::EntryType::Continuous => "file",
+        _ => "other",
+    }
+}
+
+fn is_zip_symlink(file: &zip::read::ZipFile<'_>) -> bool {
+    file.unix_mode().map_or(false, |mode| mode & 0o170000 == 0o120000)
+}
+
+fn zip_kind(file: &zip::read::ZipFile<'_>) -> &'static str {
+    if file.is_dir() {
+        "dir"
+    } else if is_zip_symlink(file) {
+        "symlink"
+    } else {
+        "file"
+    }
+}
+
+// Build an archive from `(path, contents)` pairs whose paths have already been checked
+fn build_archive(files: &[(PathBuf, Vec<u8>)], format: ArchiveFormat) -> Result<Vec<u8>, CompressError> {
+    fn append_all<W: Write>(builder: &mut tar::Builder<W>, files: &[(PathBuf, Vec<u8>)]) -> io::Result<()> {
+        for (path, contents) in files {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(contents.len() as u64);
+            header.set_mode(0o644);
+            builder.append_data(&mut header, path, &contents[..])?;
+        }
+        Ok(())
+    }
+
+    match format {
+        ArchiveFormat::Tar => {
+            let mut builder = tar::Builder::new(Vec::new());
+            append_all(&mut builder, files)?;
+            Ok(builder.into_inner()?)
+        }
+        ArchiveFormat::TarGz => {
+            let mut builder = tar::Builder::new(GzEncoder::new(Vec::new(), Compression::default()));
+            append_all(&mut builder, files)?;
+            Ok(builder.into_inner()?.finish()?)
+        }
+        ArchiveFormat::Zip => {
+            let mut writer = zip::ZipWriter::new(io::Cursor::new(Vec::new()));
+            for (path, contents) in files {
+                writer.start_file(path.to_string_lossy(), zip::write::FileOptions::default())?;
+                writer.write_all(contents)?;
+            }
+            Ok(writer.finish()?.into_inner())
+        }
+    }
+}
+
+fn list_archive(input: &[u8], format: ArchiveFormat) -> Result<Vec<ArchiveEntry>, CompressError> {
+    fn list_tar<R: Read>(reader: R) -> Result<Vec<ArchiveEntry>, CompressError> {
+        let mut archive = tar::Archive::new(reader);
+        let mut entries = Vec::new();
+        for entry in archive.entries()? {
+            let entry = entry?;
+            entries.push(ArchiveEntry {
+                path: entry.path()?.display().to_string(),
+                size: entry.header().size()?,
+                kind: tar_kind(entry.header().entry_type()),
+            });
+        }
+        Ok(entries)
+    }
+
+    match format {
+        ArchiveFormat::Tar => list_tar(input),
+        ArchiveFormat::TarGz => list_tar(GzDecoder::new(input)),
+        ArchiveFormat::Zip => {
+            let mut archive = zip::ZipArchive::new(io::Cursor::new(input))?;
+            let mut entries = Vec::new();
+            for index in 0..archive.len() {
+                let file = archive.by_index(index)?;
+                entries.push(ArchiveEntry { path: file.name().to_string(), size: file.size(), kind: zip_kind(&file) });
+            }
+            Ok(entries)
+        }
+    }
+}
+
+// Unpack an archive under `dest`, validating every path and link before anything is written
+fn extract_archive(
+    input: &[u8],
+    format: ArchiveFormat,
+    dest: &Path,
+    limits: &DecompressLimits,
+) -> Result<Vec<ArchiveEntry>, CompressError> {
+    fn extract_tar<R: Read>(reader: R, dest: &Path, budget: &mut OutputBudget<'_>) -> Result<Vec<ArchiveEntry>, CompressError> {
+        let mut archive = tar::Archive::new(reader);
+        let mut entries = Vec::new();
+        for entry in archive.entries()? {
+            let mut entry = entry?;
+            let relative = safe_entry_path(&entry.path()?)?;
+            let target = prepare_target(dest, &relative)?;
+            let entry_type = entry.header().entry_type();
+            let size = match entry_type {
+                tar::EntryType::Directory => {
+                    std::fs::create_dir_all(&target)?;
+                    0
+                }
+                tar::EntryType::Regular | tar::EntryType::Continuous => budget.copy(&mut entry, &mut File::create(&target)?)?,
+                tar::EntryType::Symlink => {
+                    let link = entry
+                        .link_name()?
+                        .ok_or_else(|| CompressError::UnsafeEntry(relative.display().to_string()))?
+                        .into_owned();
+                    check_link(&relative, &link)?;
+                    std::os::unix::fs::symlink(&link, &target)?;
+                    0
+                }
+                // Hard links and device nodes are never needed to ship a directory tree
+                _ => return Err(CompressError::UnsafeEntry(relative.display().to_string())),
+            };
+            entries.push(ArchiveEntry { path: relative.display().to_string(), size, kind: tar_kind(entry_type) });
+        }
+        Ok(entries)
+    }
+
+    let mut budget = OutputBudget::new(limits, input.len());
+    match format {
+        ArchiveFormat::Tar => extract_tar(input, dest, &mut budget),
+        ArchiveFormat::TarGz => extract_tar(GzDecoder::new(input), dest, &mut budget),
+        ArchiveFormat::Zip => {
+            let mut archive = zip::ZipArchive::new(io::Cursor::new(input))?;
+            let mut entries = Vec::new();
+            for index in 0..archive.len() {
+                let mut file = archive.by_index(index)?;
+                let relative = safe_entry_path(Path::new(file.name()))?;
+                let target = prepare_target(dest, &relative)?;
+                let kind = zip_kind(&file);
+                let size = if file.is_dir() {
+                    std::fs::create_dir_all(&target)?;
+                    0
+                } else if is_zip_symlink(&file) {
+                    let mut link = String::new();
+                    (&mut file).take(4096).read_to_string(&mut link)?;
+                    check_link(&relative, Path::new(&link))?;
+                    std::os::unix::fs::symlink(&link, &target)?;
+                    0
+                } else {
+                    budget.copy(&mut file, &mut File::create(&target)?)?
+                };
+                entries.push(ArchiveEntry { path: relative.display().to_string(), size, kind });
+            }
+            Ok(entries)
+        }
+    }
+}
+
+// Directories that archives are extracted into, one per extraction, named by a random id
+// and removed once they are older than `ttl`
+struct ExtractionStore {
+    dir: PathBuf,
+    ttl: Duration,
+}
+
+impl ExtractionStore {
+    fn prune(&self) -> io::Result<()> {
+        for entry in std::fs::read_dir(&self.dir)? {
+            let entry = entry?;
+            let age = entry.metadata()?.modified()?.elapsed().unwrap_or_default();
+            if age > self.ttl {
+                std::fs::remove_dir_all(entry.path())?;
+            }
+        }
+        Ok(())
+    }
+
+    // Resolve a file inside an extraction, following links only as far as they stay inside it
+    fn resolve(&self, id: &str, path: &Path) -> Option<PathBuf> {
+        let id = uuid::Uuid::parse_str(id).ok()?;
+        let root = std::fs::canonicalize(self.dir.join(id.simple().to_string())).ok()?;
+        let file = std::fs::canonicalize(root.join(path)).ok()?;
+        file.starts_with(&root).then_some(file)
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(crate = "rocket::serde")]
+struct Extraction {
+    id: String,
+    entries: Vec<ArchiveEntry>,
+}
+
+#[post("/archive?<format>", data = "<upload>")]
+async fn create_archive(format: &str, upload: Form<ArchiveUpload<'_>>) -> Result<(ContentType, Vec<u8>), CompressError> {
+    let format = ArchiveFormat::from_name(format).ok_or_else(|| CompressError::UnknownArchiveFormat(format.to_string()))?;
+    let mut files = Vec::new();
+    for file in &upload.files {
+        // Keep the client's relative path so directory trees survive the round trip
+        let name = file
+            .raw_name()
+            .map(|name| name.dangerous_unsafe_unsanitized_raw().as_str().to_string())
+            .unwrap_or_default();
+        let path = safe_entry_path(Path::new(&name))?;
+        let mut contents = Vec::new();
+        file.open().await?.read_to_end(&mut contents).await?;
+        files.push((path, contents));
+    }
+    Ok((format.content_type(), build_archive(&files, format)?))
+}
+
+#[post("/archive/list?<format>", format = "application/octet-stream", data = "<body>")]
+async fn list_archive_entries(
+    body: Data<'_>,
+    format: Option<&str>,
+    limits: &Limits,
+) -> Result<Json<Vec<ArchiveEntry>>, CompressError> {
+    let input = read_body(body, limits).await?;
+    let format = archive_format(format, &input)?;
+    Ok(Json(list_archive(&input, format)?))
+}
+
+#[post("/archive/extract?<format>", format = "application/octet-stream", data = "<body>")]
+async fn extract_archive_entries(
+    body: Data<'_>,
+    format: Option<&str>,
+    limits: &Limits,
+    store: &State<ExtractionStore>,
+    decompress_limits: &State<DecompressLimits>,
+) -> Result<Json<Extraction>, CompressError> {
+    let input = read_body(body, limits).await?;
+    let format = archive_format(format, &input)?;
+    if let Err(e) = store.prune() {
+        eprintln!("Failed to remove expired extractions: {}", e);
+    }
+    let id = uuid::Uuid::new_v4().simple().to_string();
+    let dest = store.dir.join(&id);
+    std::fs::create_dir(&dest)?;
+    match extract_archive(&input, format, &dest, decompress_limits) {
+        Ok(entries) => Ok(Json(Extraction { id, entries })),
+        Err(e) => {
+            // Never leave a partially extracted tree behind
+            let _ = std::fs::remove_dir_all(&dest);
+            Err(e)
+        }
+    }
+}
+
+#[get("/archive/extracted/<id>/<path..>")]
+async fn extracted_file(id: &str, path: PathBuf, store: &State<ExtractionStore>) -> Option<NamedFile> {
+    NamedFile::open(store.resolve(id, &path)?).await.ok()
 }
 
 #[get("/")]
@@ -43,24 +1405,273 @@ fn index() -> &'static str {
     "Welcome to the compression and decompression tool!"
 }
 
-// Compress the input content using gzip compression
-fn compress_content(input: &[u8]) -> Vec<u8> {
-    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
-    encoder.write_all(input).expect("Failed to write to encoder");
-    encoder.finish().expect("Failed to finish encoding");
-    encoder.get_ref().to_vec()
+// Decompress the input content with the given codec, stopping as soon as a limit is exceeded.
+// `origin_len` is the size of the original request body, against which the ratio is measured.
+fn decompress_content(
+    input: &[u8],
+    codec: Codec,
+    limits: &DecompressLimits,
+    origin_len: usize,
+) -> Result<Vec<u8>, CompressError> {
+    let decoder: Box<dyn Read + '_> = match codec {
+        Codec::Gzip => Box::new(GzDecoder::new(input)),
+        Codec::Zlib => Box::new(ZlibDecoder::new(input)),
+        Codec::Deflate => Box::new(DeflateDecoder::new(input)),
+        Codec::Zstd => Box::new(zstd::stream::read::Decoder::new(input)?),
+        Codec::Brotli => Box::new(brotli::Decompressor::new(input, 4096)),
+    };
+    read_limited(decoder, limits, origin_len)
 }
 
-// Decompress the input content using gzip decompression
-fn decompress_content(input: &[u8]) -> Vec<u8> {
-    let mut decoder = GzDecoder::new(&input[..]);
+// Drain a decoder, failing as soon as its output crosses the decompression limits
+fn read_limited<R: Read>(decoder: R, limits: &DecompressLimits, origin_len: usize) -> Result<Vec<u8>, CompressError> {
+    let cap = limits.output_cap(origin_len);
     let mut output = Vec::new();
-    decoder.read_to_end(&mut output).expect("Failed to read from decoder");
-    output
+    decoder.take(cap.saturating_add(1)).read_to_end(&mut output)?;
+    if output.len() as u64 > cap {
+        return Err(limits.exceeded(cap));
+    }
+    Ok(output)
+}
+
+// Decompress the outer layer and, when `nested` is set, keep unwrapping recognisable inner layers
+fn decompress_layers(
+    input: &[u8],
+    codec: Codec,
+    nested: bool,
+    limits: &DecompressLimits,
+) -> Result<(Vec<Codec>, Vec<u8>), CompressError> {
+    let mut codecs = vec![codec];
+    let mut output = decompress_content(input, codec, limits, input.len())?;
+    while let Some(inner) = Codec::detect(&output).filter(|_| nested) {
+        if codecs.len() >= limits.max_depth {
+            return Err(CompressError::DepthExceeded { limit: limits.max_depth });
+        }
+        // Detection is only a header heuristic, so a payload that merely looks like another
+        // layer is returned as-is; limit violations still fail the request
+        output = match decompress_content(&output, inner, limits, input.len()) {
+            Ok(decoded) => decoded,
+            Err(CompressError::Io(_)) => break,
+            Err(e) => return Err(e),
+        };
+        codecs.push(inner);
+    }
+    Ok((codecs, output))
 }
 
 #[launch]
 fn rocket() -> Rocket {
-    rocket::build()
-        .mount("/", routes![index, compress, decompress])
+    let rocket = rocket::build();
+    let decompress_limits: DecompressLimits = rocket.figment().extract_inner("decompress").unwrap_or_default();
+    let jobs_dir: PathBuf = rocket
+        .figment()
+        .extract_inner("jobs_dir")
+        .unwrap_or_else(|_| std::env::temp_dir().join("compress-jobs"));
+    std::fs::create_dir_all(&jobs_dir).expect("Failed to create the jobs directory");
+    let job_ttl: u64 = rocket.figment().extract_inner("job_ttl_secs").unwrap_or(3600);
+    let jobs = JobRegistry::open(jobs_dir, Duration::from_secs(job_ttl)).expect("Failed to clean the jobs directory");
+    let archive_dir: PathBuf = rocket
+        .figment()
+        .extract_inner("archive_dir")
+        .unwrap_or_else(|_| std::env::temp_dir().join("compress-archives"));
+    std::fs::create_dir_all(&archive_dir).expect("Failed to create the archive directory");
+    let extraction_ttl: u64 = rocket.figment().extract_inner("extraction_ttl_secs").unwrap_or(3600);
+    let extractions = ExtractionStore { dir: archive_dir, ttl: Duration::from_secs(extraction_ttl) };
+    extractions.prune().expect("Failed to clean the archive directory");
+    let dictionary_dir: PathBuf = rocket
+        .figment()
+        .extract_inner("dictionary_dir")
+        .unwrap_or_else(|_| std::env::temp_dir().join("compress-dictionaries"));
+    std::fs::create_dir_all(&dictionary_dir).expect("Failed to create the dictionary directory");
+    let dictionaries = DictionaryStore::load(dictionary_dir).expect("Failed to load zstd dictionaries");
+    rocket
+        .attach(ResponseCompression::default())
+        .manage(decompress_limits)
+        .manage(jobs)
+        .manage(extractions)
+        .manage(dictionaries)
+        .mount("/", routes![
+            index,
+            compress,
+            compress_json,
+            decompress,
+            decompress_json,
+            benchmark,
+            train_dictionary,
+            list_dictionaries,
+            compress_with_dictionary,
+            decompress_with_dictionary,
+            compress_stream,
+            decompress_stream,
+            compress_job,
+            decompress_job,
+            job_status,
+            job_result,
+            create_archive,
+            list_archive_entries,
+            extract_archive_entries,
+            extracted_file,
+        ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn symlink_entry(builder: &mut tar::Builder<Vec<u8>>, path: &str, target: &str) {
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(tar::EntryType::Symlink);
+        header.set_size(0);
+        header.set_mode(0o777);
+        header.set_link_name(target).unwrap();
+        builder.append_data(&mut header, path, io::empty()).unwrap();
+    }
+
+    #[test]
+    fn test_chained_symlinks_cannot_escape_the_extraction() {
+        let mut builder = tar::Builder::new(Vec::new());
+        symlink_entry(&mut builder, "a/l1", "..");
+        symlink_entry(&mut builder, "d/e/l2", "../../a/l1/a/l1/../../../../etc/passwd");
+        let tarball = builder.into_inner().unwrap();
+
+        let dest = std::env::temp_dir().join(format!("extract-test-{}", uuid::Uuid::new_v4().simple()));
+        std::fs::create_dir_all(&dest).unwrap();
+        let result = extract_archive(&tarball, ArchiveFormat::Tar, &dest, &DecompressLimits::default());
+        std::fs::remove_dir_all(&dest).unwrap();
+
+        assert!(matches!(result, Err(CompressError::UnsafeEntry(_))));
+    }
+
+    #[test]
+    fn test_extracted_files_resolve_inside_their_extraction() {
+        let store = ExtractionStore {
+            dir: std::env::temp_dir().join(format!("extract-store-{}", uuid::Uuid::new_v4().simple())),
+            ttl: Duration::from_secs(60),
+        };
+        let id = uuid::Uuid::new_v4().simple().to_string();
+        let root = store.dir.join(&id);
+        std::fs::create_dir_all(&root).unwrap();
+        std::fs::write(root.join("inside.txt"), b"ok").unwrap();
+        std::fs::write(store.dir.join("outside.txt"), b"secret").unwrap();
+        std::os::unix::fs::symlink("../outside.txt", root.join("escape")).unwrap();
+
+        let inside = store.resolve(&id, Path::new("inside.txt"));
+        let escape = store.resolve(&id, Path::new("escape"));
+        let bad_id = store.resolve("..", Path::new("outside.txt"));
+        std::fs::remove_dir_all(&store.dir).unwrap();
+
+        assert!(inside.is_some());
+        assert!(escape.is_none());
+        assert!(bad_id.is_none());
+    }
+
+    fn gzip_bomb(size: usize) -> Vec<u8> {
+        compress_content(&vec![0u8; size], Codec::Gzip, 9).unwrap()
+    }
+
+    #[test]
+    fn test_codec_detect_reads_magic_bytes() {
+        let text = b"detect me, detect me, detect me";
+        for codec in [Codec::Gzip, Codec::Zlib, Codec::Zstd] {
+            let level = codec.levels().2;
+            assert_eq!(Codec::detect(&compress_content(text, codec, level).unwrap()), Some(codec));
+        }
+        // Raw deflate and brotli have no signature
+        assert_eq!(Codec::detect(&compress_content(text, Codec::Deflate, 6).unwrap()), None);
+        assert_eq!(Codec::detect(&compress_content(text, Codec::Brotli, 5).unwrap()), None);
+        assert_eq!(Codec::detect(text), None);
+        assert_eq!(Codec::detect(&[0x1f]), None);
+    }
+
+    #[test]
+    fn test_gzip_bomb_trips_the_ratio_limit() {
+        let bomb = gzip_bomb(16 * 1024 * 1024);
+        let result = decompress_layers(&bomb, Codec::Gzip, false, &DecompressLimits::default());
+        let error = result.unwrap_err();
+        assert!(matches!(error, CompressError::RatioExceeded { limit: 100 }));
+        assert_eq!(error.limit(), Some("max_ratio"));
+        assert_eq!(error.status(), Status::UnprocessableEntity);
+    }
+
+    #[test]
+    fn test_output_size_limit_is_enforced() {
+        let limits = DecompressLimits { max_output_bytes: 4096, max_ratio: 100_000, max_depth: 3 };
+        let bomb = gzip_bomb(1024 * 1024);
+        let error = read_limited(GzDecoder::new(&bomb[..]), &limits, bomb.len()).unwrap_err();
+        assert!(matches!(error, CompressError::OutputTooLarge { limit: 4096 }));
+        assert_eq!(error.limit(), Some("max_output_bytes"));
+        assert_eq!(error.status(), Status::PayloadTooLarge);
+
+        // Output exactly at the cap is still allowed
+        let exact = compress_content(&[7u8; 4096], Codec::Gzip, 6).unwrap();
+        assert_eq!(read_limited(GzDecoder::new(&exact[..]), &limits, exact.len()).unwrap().len(), 4096);
+    }
+
+    #[test]
+    fn test_nested_zlib_in_gzip_is_unwrapped() {
+        let text = b"a zlib stream inside a gzip stream";
+        let inner = compress_content(text, Codec::Zlib, 6).unwrap();
+        let outer = compress_content(&inner, Codec::Gzip, 6).unwrap();
+        let limits = DecompressLimits::default();
+
+        let (codecs, output) = decompress_layers(&outer, Codec::Gzip, true, &limits).unwrap();
+        assert_eq!(codecs, vec![Codec::Gzip, Codec::Zlib]);
+        assert_eq!(output, text);
+
+        let (codecs, output) = decompress_layers(&outer, Codec::Gzip, false, &limits).unwrap();
+        assert_eq!(codecs, vec![Codec::Gzip]);
+        assert_eq!(output, inner);
+    }
+
+    #[test]
+    fn test_layers_past_the_depth_limit_are_refused() {
+        let mut payload = b"deeply wrapped".to_vec();
+        for _ in 0..4 {
+            payload = compress_content(&payload, Codec::Gzip, 6).unwrap();
+        }
+        let limits = DecompressLimits::default();
+        let error = decompress_layers(&payload, Codec::Gzip, true, &limits).unwrap_err();
+        assert!(matches!(error, CompressError::DepthExceeded { limit: 3 }));
+        assert_eq!(error.limit(), Some("max_depth"));
+
+        let limits = DecompressLimits { max_depth: 4, ..limits };
+        let (codecs, output) = decompress_layers(&payload, Codec::Gzip, true, &limits).unwrap();
+        assert_eq!(codecs.len(), 4);
+        assert_eq!(output, b"deeply wrapped");
+    }
+
+    #[test]
+    fn test_jobs_report_the_limit_that_failed_them() {
+        let dir = std::env::temp_dir().join(format!("job-test-{}", uuid::Uuid::new_v4().simple()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let input = dir.join("bomb.in");
+        let output = dir.join("bomb.out");
+        std::fs::write(&input, gzip_bomb(16 * 1024 * 1024)).unwrap();
+
+        let bytes_in = Arc::new(AtomicU64::new(0));
+        let result = run_job(&input, &output, Operation::Decompress, Codec::Gzip, bytes_in, DecompressLimits::default());
+        let output_left = output.exists();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let error = CompressError::from(result.unwrap_err());
+        assert!(matches!(error, CompressError::RatioExceeded { .. }));
+        assert_eq!(error.limit(), Some("max_ratio"));
+        assert!(!output_left);
+    }
+
+    #[rocket::async_test]
+    async fn test_streamed_bombs_fail_before_the_response_starts() {
+        let bomb = gzip_bomb(16 * 1024 * 1024);
+        let consumed = Arc::new(AtomicU64::new(0));
+        let reader = AsyncBufReader::new(CountingReader::new(&bomb[..], consumed.clone()));
+        let decoded = LimitedReader::new(decode_stream(reader, Codec::Gzip), consumed, DecompressLimits::default());
+        let error = prefetch(Box::pin(decoded)).await.err().unwrap();
+        assert_eq!(error.limit(), Some("max_ratio"));
+
+        // Small outputs pass through whole
+        let small = compress_content(b"streamed", Codec::Gzip, 6).unwrap();
+        let mut output = Vec::new();
+        prefetch(decode_stream(&small[..], Codec::Gzip)).await.unwrap().read_to_end(&mut output).await.unwrap();
+        assert_eq!(output, b"streamed");
+    }
 }
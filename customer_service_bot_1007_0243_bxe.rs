@@ -1,22 +1,984 @@
 // customer_service_bot.rs
 // Customer Service Bot using Rust and Rocket framework
-use rocket::get;
+use rocket::fairing::AdHoc;
+use rocket::{get, post};
+use rocket::http::{ContentType, Cookie, Status};
+use rocket::request::{self, FromRequest, Request};
+use rocket::response::stream::{Event, EventStream};
 use rocket::serde::json::Json;
+use rocket::tokio;
+use rocket::tokio::select;
+use rocket::tokio::sync::broadcast;
+use rocket::Shutdown;
 use rocket::State;
-use serde::Deserialize;
-use std::sync::Mutex;
-use lazy_static::lazy_static;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::io;
+use std::io::{BufRead, Read, Seek, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
-// Define a struct for our bot state, wrapped in Mutex for thread safety
-#[macro_use]
-extern crate lazy_static;
+// Name of the cookie and header that carry the session id
+const SESSION_COOKIE: &str = "bot_session";
+const SESSION_HEADER: &str = "X-Session-Id";
 
+// Phrases that mean the customer wants to talk to a person
+const HUMAN_REQUEST_KEYWORDS: [&str; 5] = ["human", "real person", "agent", "人工", "真人"];
+
+// Split lowercased text into the words that English keywords are matched against
+fn split_words(lowered: &str) -> Vec<&str> {
+    lowered.split(|c: char| !(c.is_ascii_alphanumeric() || c == '\'')).filter(|w| !w.is_empty()).collect()
+}
+
+// Whether a lowercase keyword occurs in the text. English keywords and phrases match whole
+// words, so "agent" does not fire on "management"; Chinese keywords match anywhere.
+fn has_keyword(lowered: &str, words: &[&str], keyword: &str) -> bool {
+    if keyword.is_ascii() {
+        let phrase = split_words(keyword);
+        !phrase.is_empty() && words.windows(phrase.len()).any(|window| window == phrase.as_slice())
+    } else {
+        lowered.contains(keyword)
+    }
+}
+
+// Whether the customer asks for a person
+fn asks_for_human(lowered: &str) -> bool {
+    let words = split_words(lowered);
+    HUMAN_REQUEST_KEYWORDS.iter().any(|keyword| has_keyword(lowered, &words, keyword))
+}
+
+// Seconds since the Unix epoch
+fn now_secs() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or_default()
+}
+
+// Lexicons for the sentiment and urgency scorer, with per-word weights.
+// English entries match whole words; Chinese entries match anywhere in the text.
+const SENTIMENT_LEXICON: [(&str, f64); 36] = [
+    ("thanks", 1.0), ("thank", 1.0), ("great", 1.5), ("good", 1.0), ("perfect", 2.0), ("love", 2.0),
+    ("helpful", 1.5), ("happy", 1.5), ("awesome", 2.0), ("excellent", 2.0),
+    ("bad", -1.0), ("terrible", -2.5), ("awful", -2.5), ("horrible", -2.5), ("worst", -3.0), ("angry", -2.5),
+    ("ridiculous", -2.0), ("useless", -2.5), ("scam", -3.0), ("hate", -3.0), ("disappointed", -2.0),
+    ("unacceptable", -2.5), ("furious", -3.0), ("refund", -0.5),
+    ("谢谢", 1.0), ("感谢", 1.0), ("很好", 1.5), ("满意", 1.5), ("不错", 1.0),
+    ("差", -1.5), ("垃圾", -3.0), ("骗子", -3.0), ("生气", -2.5), ("投诉", -2.0), ("失望", -2.0), ("太慢", -1.5),
+];
+
+const URGENCY_LEXICON: [(&str, f64); 14] = [
+    ("urgent", 2.0), ("asap", 2.0), ("immediately", 2.0), ("now", 0.5), ("emergency", 3.0), ("today", 0.5),
+    ("still", 0.5), ("again", 0.5),
+    ("紧急", 2.0), ("马上", 1.5), ("立刻", 2.0), ("赶紧", 1.5), ("尽快", 1.5), ("还没", 1.0),
+];
+
+const NEGATORS: [&str; 8] = ["not", "no", "never", "don't", "isn't", "wasn't", "不", "没"];
+const INTENSIFIERS: [&str; 7] = ["very", "so", "really", "extremely", "非常", "太", "特别"];
+
+// A sentiment at or below this on one message, or a session mood at or below
+// `ANGRY_MOOD`, hands the conversation to an agent
+const ANGRY_MESSAGE: f64 = -0.6;
+const ANGRY_MOOD: f64 = -0.4;
+const URGENT: f64 = 0.6;
+
+// How a message reads: `sentiment` in -1..=1 (negative is unhappy), `urgency` in 0..=1
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+struct MessageScore {
+    sentiment: f64,
+    urgency: f64,
+}
+
+impl MessageScore {
+    fn score(text: &str) -> MessageScore {
+        let lowered = text.to_lowercase();
+        let mut sentiment = 0.0;
+        let mut urgency = 0.0;
+
+        // English: walk the words, letting a negator or intensifier shape the next lexicon hit
+        let mut modifier = 1.0;
+        for word in lowered.split(|c: char| !(c.is_ascii_alphanumeric() || c == '\'')).filter(|w| !w.is_empty()) {
+            if NEGATORS.contains(&word) {
+                modifier *= -1.0;
+                continue;
+            }
+            if INTENSIFIERS.contains(&word) {
+                modifier *= 1.5;
+                continue;
+            }
+            if let Some(&(_, weight)) = SENTIMENT_LEXICON.iter().find(|(entry, _)| *entry == word) {
+                sentiment += weight * modifier;
+            }
+            if let Some(&(_, weight)) = URGENCY_LEXICON.iter().find(|(entry, _)| *entry == word) {
+                urgency += weight;
+            }
+            modifier = 1.0;
+        }
+
+        // Chinese: look each entry up in place and check the character just before it
+        let cjk = |entry: &str| !entry.is_ascii();
+        for &(entry, weight) in SENTIMENT_LEXICON.iter().filter(|(entry, _)| cjk(entry)) {
+            for (at, _) in lowered.match_indices(entry) {
+                let before = &lowered[..at];
+                let negated = NEGATORS.iter().filter(|n| cjk(n)).any(|n| before.ends_with(n));
+                let intensified = INTENSIFIERS.iter().filter(|i| cjk(i)).any(|i| before.ends_with(i));
+                let modifier = if negated { -1.0 } else if intensified { 1.5 } else { 1.0 };
+                sentiment += weight * modifier;
+            }
+        }
+        for &(entry, weight) in URGENCY_LEXICON.iter().filter(|(entry, _)| cjk(entry)) {
+            urgency += weight * lowered.matches(entry).count() as f64;
+        }
+
+        // Shouting reads as both angrier and more urgent
+        let exclamations = text.chars().filter(|&c| c == '!' || c == '！').count() as f64;
+        let letters: Vec<char> = text.chars().filter(|c| c.is_ascii_alphabetic()).collect();
+        let shouting = letters.len() >= 8 && letters.iter().all(|c| c.is_ascii_uppercase());
+        urgency += exclamations * 0.5 + if shouting { 1.0 } else { 0.0 };
+        if sentiment < 0.0 {
+            sentiment -= exclamations * 0.5 + if shouting { 1.0 } else { 0.0 };
+        }
+
+        MessageScore { sentiment: sentiment / (sentiment.abs() + 2.0), urgency: urgency / (urgency + 2.0) }
+    }
+
+    fn is_angry(&self) -> bool {
+        self.sentiment <= ANGRY_MESSAGE
+    }
+
+    fn is_urgent(&self) -> bool {
+        self.urgency >= URGENT
+    }
+
+    // The least priority a ticket raised for this message should have
+    fn priority(&self) -> Priority {
+        match (self.is_angry(), self.is_urgent()) {
+            (true, true) => Priority::Urgent,
+            (true, false) | (false, true) => Priority::High,
+            _ => Priority::Low,
+        }
+    }
+}
+
+// Who wrote a message
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Role {
+    User,
+    Bot,
+    Agent,
+}
+
+impl Role {
+    fn name(self) -> &'static str {
+        match self {
+            Role::User => "user",
+            Role::Bot => "bot",
+            Role::Agent => "agent",
+        }
+    }
+}
+
+// A single line of a conversation
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct ChatMessage {
+    role: Role,
+    // The agent who wrote the message, for `agent` messages
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    author: Option<String>,
+    // The intent a bot reply answered; `None` on a bot reply means the bot fell back
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    intent: Option<String>,
+    // Sentiment and urgency of a customer message
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    score: Option<MessageScore>,
+    // The escalation ticket a bot reply raised or updated
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    ticket: Option<u64>,
+    text: String,
+    // Seconds since the Unix epoch
+    timestamp: u64,
+}
+
+impl ChatMessage {
+    fn new(role: Role, text: String) -> Self {
+        ChatMessage { role, author: None, intent: None, score: None, ticket: None, text, timestamp: now_secs() }
+    }
+
+    fn from_user(text: String, score: MessageScore) -> Self {
+        ChatMessage { score: Some(score), ..ChatMessage::new(Role::User, text) }
+    }
+
+    fn from_bot(text: String, intent: Option<String>, ticket: Option<u64>) -> Self {
+        ChatMessage { intent, ticket, ..ChatMessage::new(Role::Bot, text) }
+    }
+
+    fn from_agent(agent: &str, text: String) -> Self {
+        ChatMessage { author: Some(agent.to_string()), ..ChatMessage::new(Role::Agent, text) }
+    }
+}
+
+// A transcript line as kept in the conversation log
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct LoggedMessage {
+    session_id: String,
+    #[serde(flatten)]
+    message: ChatMessage,
+}
+
+// Every message of every conversation, kept after sessions expire for analytics and export.
+// With a configured path the log is a JSONL file that readers scan from disk; without one
+// only the most recent `MEMORY_LOG_LINES` lines are kept in memory.
+struct TranscriptLog {
+    path: Option<PathBuf>,
+    file: Option<std::fs::File>,
+    lines: VecDeque<LoggedMessage>,
+}
+
+const MEMORY_LOG_LINES: usize = 100_000;
+
+impl TranscriptLog {
+    fn open(path: Option<&Path>) -> io::Result<TranscriptLog> {
+        let Some(path) = path else {
+            return Ok(TranscriptLog { path: None, file: None, lines: VecDeque::new() });
+        };
+        let mut file = std::fs::OpenOptions::new().create(true).read(true).append(true).open(path)?;
+        // A crash can leave a half-written last line; end it so the next append starts cleanly
+        let len = file.metadata()?.len();
+        if len > 0 {
+            let mut last = [0u8; 1];
+            file.seek(io::SeekFrom::Start(len - 1))?;
+            file.read_exact(&mut last)?;
+            if last[0] != b'\n' {
+                file.write_all(b"\n")?;
+            }
+        }
+        Ok(TranscriptLog { path: Some(path.to_path_buf()), file: Some(file), lines: VecDeque::new() })
+    }
+
+    fn append(&mut self, session_id: &str, message: &ChatMessage) {
+        let line = LoggedMessage { session_id: session_id.to_string(), message: message.clone() };
+        match self.file.as_mut() {
+            Some(file) => {
+                let written = serde_json::to_string(&line)
+                    .map_err(io::Error::from)
+                    .and_then(|json| writeln!(file, "{}", json));
+                if let Err(e) = written {
+                    eprintln!("Failed to append to the transcript log: {}", e);
+                }
+            }
+            None => {
+                if self.lines.len() == MEMORY_LOG_LINES {
+                    self.lines.pop_front();
+                }
+                self.lines.push_back(line);
+            }
+        }
+    }
+
+    // Capture what a reader needs while the state lock is held; the reading happens later
+    fn snapshot(&self, from: Option<u64>, to: Option<u64>) -> TranscriptSnapshot {
+        match &self.path {
+            Some(path) => TranscriptSnapshot::File { path: path.clone(), from, to },
+            None => TranscriptSnapshot::Memory(self.lines.iter().filter(|line| in_range(line, from, to)).cloned().collect()),
+        }
+    }
+}
+
+fn in_range(line: &LoggedMessage, from: Option<u64>, to: Option<u64>) -> bool {
+    from.map_or(true, |from| line.message.timestamp >= from) && to.map_or(true, |to| line.message.timestamp < to)
+}
+
+enum TranscriptSnapshot {
+    File { path: PathBuf, from: Option<u64>, to: Option<u64> },
+    Memory(Vec<LoggedMessage>),
+}
+
+impl TranscriptSnapshot {
+    // Lines whose timestamp falls in `from..to`; either bound may be open.
+    // Lines that fail to parse, such as one cut short by a crash, are skipped with a warning.
+    fn load(self) -> io::Result<Vec<LoggedMessage>> {
+        let (path, from, to) = match self {
+            TranscriptSnapshot::Memory(lines) => return Ok(lines),
+            TranscriptSnapshot::File { path, from, to } => (path, from, to),
+        };
+        let mut lines = Vec::new();
+        let mut skipped = 0;
+        for line in io::BufReader::new(std::fs::File::open(&path)?).lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            match serde_json::from_str::<LoggedMessage>(&line) {
+                Ok(logged) if in_range(&logged, from, to) => lines.push(logged),
+                Ok(_) => {}
+                Err(_) => skipped += 1,
+            }
+        }
+        if skipped > 0 {
+            eprintln!("Skipped {} unreadable lines in the transcript log {}", skipped, path.display());
+        }
+        Ok(lines)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum Priority {
+    Low,
+    Normal,
+    High,
+    Urgent,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum TicketState {
+    Open,
+    Claimed,
+    Closed,
+}
+
+// A conversation handed over to the human agents
+#[derive(Debug, Clone, Serialize)]
+struct Ticket {
+    id: u64,
+    session_id: String,
+    priority: Priority,
+    state: TicketState,
+    // The agent who claimed the ticket
+    agent: Option<String>,
+    reason: String,
+    // The customer message that caused the escalation
+    question: String,
+    created_at: u64,
+}
+
+impl Ticket {
+    fn is_active(&self) -> bool {
+        self.state != TicketState::Closed
+    }
+}
+
+// Something pushed to the clients listening on a session's chat channel
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ChannelEvent {
+    // A new transcript line; `index` is its position and doubles as the SSE event id
+    Message { index: usize, message: ChatMessage },
+    Typing {
+        role: Role,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        author: Option<String>,
+        typing: bool,
+    },
+}
+
+impl ChannelEvent {
+    fn to_sse(&self) -> Event {
+        match self {
+            ChannelEvent::Message { index, message } => Event::json(message).event("message").id(index.to_string()),
+            ChannelEvent::Typing { .. } => Event::json(self).event("typing"),
+        }
+    }
+}
+
+// The history of one conversation
+struct Session {
+    messages: Vec<ChatMessage>,
+    // Live listeners of this conversation; dropping the session ends their streams
+    events: broadcast::Sender<ChannelEvent>,
+    // Slot values seen so far, so follow-up questions can refer back to them
+    slots: HashMap<String, String>,
+    // The escalation ticket for this conversation, if one was raised
+    ticket: Option<u64>,
+    // Moving average of the customer's sentiment, so a conversation that sours is noticed
+    mood: f64,
+    last_active: Instant,
+}
+
+impl Session {
+    // Append a message to the transcript and push it to any listeners
+    fn push(&mut self, message: ChatMessage) {
+        let index = self.messages.len();
+        self.messages.push(message.clone());
+        // Sending only fails when nobody is listening
+        let _ = self.events.send(ChannelEvent::Message { index, message });
+    }
+
+    fn typing(&self, role: Role, author: Option<String>, typing: bool) {
+        let _ = self.events.send(ChannelEvent::Typing { role, author, typing });
+    }
+
+    // The transcript from `start` on, as channel events
+    fn replay(&self, start: usize) -> Vec<ChannelEvent> {
+        self.messages
+            .iter()
+            .enumerate()
+            .skip(start)
+            .map(|(index, message)| ChannelEvent::Message { index, message: message.clone() })
+            .collect()
+    }
+}
+
+// Shared bot state: the open sessions, the escalation queue, the conversation log
+// and how long an idle session is kept
 struct BotState {
-    // This could hold any shared state, for simplicity, it's empty
+    sessions: HashMap<String, Session>,
+    tickets: HashMap<u64, Ticket>,
+    next_ticket: u64,
+    log: TranscriptLog,
+    idle_timeout: Duration,
+}
+
+impl BotState {
+    fn new(idle_timeout: Duration, log: TranscriptLog) -> Self {
+        BotState { sessions: HashMap::new(), tickets: HashMap::new(), next_ticket: 1, log, idle_timeout }
+    }
+
+    // Add a message to a session's transcript and to the conversation log
+    fn push(&mut self, session_id: &str, message: ChatMessage) {
+        self.log.append(session_id, &message);
+        self.session(session_id).push(message);
+    }
+
+    // Drop sessions that have been idle for longer than the timeout,
+    // keeping those still waiting on an agent
+    fn expire_idle(&mut self) {
+        let idle_timeout = self.idle_timeout;
+        let tickets = &self.tickets;
+        self.sessions.retain(|_, session| {
+            let waiting = session.ticket.and_then(|id| tickets.get(&id)).map_or(false, Ticket::is_active);
+            waiting || session.last_active.elapsed() < idle_timeout
+        });
+        // Closed tickets are only kept while their conversation is still around
+        let sessions = &self.sessions;
+        self.tickets.retain(|_, ticket| ticket.is_active() || sessions.contains_key(&ticket.session_id));
+    }
+
+    // The unclosed ticket for a session, if any
+    fn active_ticket(&self, session_id: &str) -> Option<&Ticket> {
+        let id = self.sessions.get(session_id)?.ticket?;
+        self.tickets.get(&id).filter(|ticket| ticket.is_active())
+    }
+
+    // Put a session in the escalation queue, or raise the priority of its existing ticket
+    fn escalate(&mut self, session_id: &str, priority: Priority, reason: &str, question: &str) -> u64 {
+        if let Some(id) = self.active_ticket(session_id).map(|ticket| ticket.id) {
+            let ticket = self.tickets.get_mut(&id).expect("active ticket exists");
+            ticket.priority = ticket.priority.max(priority);
+            return id;
+        }
+        let id = self.next_ticket;
+        self.next_ticket += 1;
+        self.tickets.insert(id, Ticket {
+            id,
+            session_id: session_id.to_string(),
+            priority,
+            state: TicketState::Open,
+            agent: None,
+            reason: reason.to_string(),
+            question: question.to_string(),
+            created_at: now_secs(),
+        });
+        self.session(session_id).ticket = Some(id);
+        id
+    }
+
+    // Fetch a session, opening a fresh one if it is new or has expired
+    fn session(&mut self, id: &str) -> &mut Session {
+        self.expire_idle();
+        let session = self
+            .sessions
+            .entry(id.to_string())
+            .or_insert_with(|| Session {
+                messages: Vec::new(),
+                events: broadcast::channel(64).0,
+                slots: HashMap::new(),
+                ticket: None,
+                mood: 0.0,
+                last_active: Instant::now(),
+            });
+        session.last_active = Instant::now();
+        session
+    }
+}
+
+// Errors raised while loading the rules file
+#[derive(Debug)]
+enum RuleError {
+    Io(io::Error),
+    Yaml(serde_yaml::Error),
+    Json(serde_json::Error),
+    Regex { intent: String, error: regex::Error },
+}
+
+impl fmt::Display for RuleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RuleError::Io(e) => write!(f, "failed to read rules: {}", e),
+            RuleError::Yaml(e) => write!(f, "invalid YAML rules: {}", e),
+            RuleError::Json(e) => write!(f, "invalid JSON rules: {}", e),
+            RuleError::Regex { intent, error } => write!(f, "invalid pattern in intent {}: {}", intent, error),
+        }
+    }
+}
+
+impl std::error::Error for RuleError {}
+
+// An intent as written in the rules file
+#[derive(Debug, Deserialize)]
+struct IntentConfig {
+    name: String,
+    #[serde(default)]
+    priority: i32,
+    #[serde(default)]
+    keywords: Vec<String>,
+    #[serde(default)]
+    patterns: Vec<String>,
+    // Slot name to extraction regex; the first capture group is used when there is one
+    #[serde(default)]
+    slots: HashMap<String, String>,
+    // Response template; `{slot}` and `{message}` placeholders are filled in
+    response: String,
+}
+
+// The rules file: intents plus the reply used when none of them match
+#[derive(Debug, Deserialize)]
+struct RulesConfig {
+    #[serde(default = "default_fallback")]
+    fallback: String,
+    #[serde(default)]
+    intents: Vec<IntentConfig>,
+}
+
+fn default_fallback() -> String {
+    "Sorry, I didn't understand that. Could you rephrase?".to_string()
+}
+
+struct Intent {
+    name: String,
+    priority: i32,
+    keywords: Vec<String>,
+    patterns: Vec<Regex>,
+    slots: Vec<(String, Regex)>,
+    response: String,
+}
+
+impl Intent {
+    fn compile(config: IntentConfig) -> Result<Intent, RuleError> {
+        let compile = |pattern: &str| {
+            Regex::new(pattern).map_err(|error| RuleError::Regex { intent: config.name.clone(), error })
+        };
+        let patterns = config.patterns.iter().map(|p| compile(p)).collect::<Result<_, _>>()?;
+        let slots = config
+            .slots
+            .iter()
+            .map(|(name, pattern)| Ok((name.clone(), compile(pattern)?)))
+            .collect::<Result<_, RuleError>>()?;
+        Ok(Intent {
+            keywords: config.keywords.iter().map(|k| k.to_lowercase()).collect(),
+            name: config.name,
+            priority: config.priority,
+            patterns,
+            slots,
+            response: config.response,
+        })
+    }
+
+    // How strongly the message matches: the number of keyword and pattern hits
+    fn score(&self, message: &str) -> usize {
+        let lowered = message.to_lowercase();
+        let words = split_words(&lowered);
+        let keyword_hits = self.keywords.iter().filter(|k| has_keyword(&lowered, &words, k)).count();
+        let pattern_hits = self.patterns.iter().filter(|p| p.is_match(message)).count();
+        keyword_hits + pattern_hits
+    }
+
+    fn extract_slots(&self, message: &str) -> HashMap<String, String> {
+        self.slots
+            .iter()
+            .filter_map(|(name, pattern)| {
+                let captures = pattern.captures(message)?;
+                let value = captures.get(1).or_else(|| captures.get(0))?;
+                Some((name.clone(), value.as_str().to_string()))
+            })
+            .collect()
+    }
+}
+
+// A compiled rules file
+struct RuleSet {
+    fallback: String,
+    intents: Vec<Intent>,
+}
+
+// What the rule engine decided for one message
+struct Reply {
+    intent: Option<String>,
+    text: String,
+}
+
+impl RuleSet {
+    fn empty() -> RuleSet {
+        RuleSet { fallback: default_fallback(), intents: Vec::new() }
+    }
+
+    // Parse a YAML or JSON rules file, picking the format from the extension
+    fn from_file(path: &Path) -> Result<RuleSet, RuleError> {
+        let text = std::fs::read_to_string(path).map_err(RuleError::Io)?;
+        let config: RulesConfig = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("json") => serde_json::from_str(&text).map_err(RuleError::Json)?,
+            _ => serde_yaml::from_str(&text).map_err(RuleError::Yaml)?,
+        };
+        let intents = config.intents.into_iter().map(Intent::compile).collect::<Result<_, _>>()?;
+        Ok(RuleSet { fallback: config.fallback, intents })
+    }
+
+    // Answer a message with the best matching intent, highest priority first.
+    // Slots found in the message are remembered in `slots`, so a follow-up question
+    // may reuse an order number given earlier in the conversation.
+    fn respond(&self, message: &str, slots: &mut HashMap<String, String>) -> Reply {
+        let best = self
+            .intents
+            .iter()
+            .map(|intent| (intent, intent.score(message)))
+            .filter(|&(_, score)| score > 0)
+            .max_by_key(|&(intent, score)| (intent.priority, score));
+        let Some((intent, _)) = best else {
+            return Reply { intent: None, text: self.fallback.clone() };
+        };
+        slots.extend(intent.extract_slots(message));
+        let text = fill_template(&intent.response, message, slots);
+        Reply { intent: Some(intent.name.clone()), text }
+    }
+}
+
+// Substitute `{message}` and `{slot}` placeholders in a single pass, so braces in the
+// substituted text are never expanded again. Unknown placeholders are left as they are.
+fn fill_template(template: &str, message: &str, slots: &HashMap<String, String>) -> String {
+    let mut text = String::with_capacity(template.len() + message.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        text.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        let Some(end) = after.find('}') else {
+            rest = &rest[start..];
+            break;
+        };
+        let name = &after[..end];
+        match name {
+            "message" => text.push_str(message),
+            _ => match slots.get(name) {
+                Some(value) => text.push_str(value),
+                None => text.push_str(&rest[start..start + end + 2]),
+            },
+        }
+        rest = &after[end + 1..];
+    }
+    text.push_str(rest);
+    text
+}
+
+// The rules currently in effect, reloaded whenever the file on disk changes.
+// A missing file leaves the bot on the fallback reply until one is created.
+#[derive(Clone)]
+struct RuleStore {
+    path: PathBuf,
+    // The active rules and the modification time of the last version read, loaded or not
+    loaded: Arc<Mutex<(Arc<RuleSet>, Option<SystemTime>)>>,
+}
+
+impl RuleStore {
+    fn load(path: PathBuf) -> Result<RuleStore, RuleError> {
+        let store = RuleStore { path, loaded: Arc::new(Mutex::new((Arc::new(RuleSet::empty()), None))) };
+        store.reload_if_changed()?;
+        Ok(store)
+    }
+
+    fn current(&self) -> Arc<RuleSet> {
+        self.loaded.lock().unwrap().0.clone()
+    }
+
+    // Reload the rules if the file's modification time moved; on error the old rules stay active
+    fn reload_if_changed(&self) -> Result<bool, RuleError> {
+        let modified = match std::fs::metadata(&self.path) {
+            Ok(metadata) => Some(metadata.modified().map_err(RuleError::Io)?),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+            Err(e) => return Err(RuleError::Io(e)),
+        };
+        if modified.is_none() || modified == self.loaded.lock().unwrap().1 {
+            return Ok(false);
+        }
+        let rules = match RuleSet::from_file(&self.path) {
+            Ok(rules) => rules,
+            Err(e) => {
+                // Remember the broken version, so it is reported once rather than on every poll
+                self.loaded.lock().unwrap().1 = modified;
+                return Err(e);
+            }
+        };
+        *self.loaded.lock().unwrap() = (Arc::new(rules), modified);
+        Ok(true)
+    }
+}
+
+// One question and answer from the FAQ corpus
+#[derive(Debug, Clone, Deserialize)]
+struct FaqEntry {
+    question: String,
+    answer: String,
+}
+
+// Split text into search terms: lowercase ASCII words, plus single characters
+// and overlapping bigrams for runs of CJK text, which has no spaces between words.
+fn tokenize(text: &str) -> Vec<String> {
+    fn is_cjk(c: char) -> bool {
+        matches!(c, '\u{4e00}'..='\u{9fff}' | '\u{3400}'..='\u{4dbf}' | '\u{f900}'..='\u{faff}')
+    }
+
+    let mut tokens = Vec::new();
+    let mut word = String::new();
+    let mut cjk_run: Vec<char> = Vec::new();
+    let flush_cjk = |run: &mut Vec<char>, tokens: &mut Vec<String>| {
+        tokens.extend(run.iter().map(|c| c.to_string()));
+        tokens.extend(run.windows(2).map(|pair| pair.iter().collect::<String>()));
+        run.clear();
+    };
+    for c in text.chars() {
+        if is_cjk(c) {
+            if !word.is_empty() {
+                tokens.push(std::mem::take(&mut word));
+            }
+            cjk_run.push(c);
+        } else {
+            flush_cjk(&mut cjk_run, &mut tokens);
+            if c.is_alphanumeric() {
+                word.extend(c.to_lowercase());
+            } else if !word.is_empty() {
+                tokens.push(std::mem::take(&mut word));
+            }
+        }
+    }
+    flush_cjk(&mut cjk_run, &mut tokens);
+    if !word.is_empty() {
+        tokens.push(word);
+    }
+    tokens
+}
+
+// Read FAQ entries from a JSON array of `{question, answer}` objects, or from Markdown
+// where every `#` heading is a question and the text below it is the answer.
+fn load_faq_file(path: &Path) -> io::Result<Vec<FaqEntry>> {
+    let text = std::fs::read_to_string(path)?;
+    if path.extension().map_or(false, |ext| ext == "json") {
+        return serde_json::from_str(&text).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e));
+    }
+    let mut entries = Vec::new();
+    let mut current: Option<FaqEntry> = None;
+    for line in text.lines() {
+        if let Some(heading) = line.strip_prefix('#') {
+            entries.extend(current.take());
+            let question = heading.trim_start_matches('#').trim().to_string();
+            current = Some(FaqEntry { question, answer: String::new() });
+        } else if let Some(entry) = current.as_mut() {
+            entry.answer.push_str(line);
+            entry.answer.push('\n');
+        }
+    }
+    entries.extend(current);
+    for entry in &mut entries {
+        entry.answer = entry.answer.trim().to_string();
+    }
+    entries.retain(|entry| !entry.answer.is_empty());
+    Ok(entries)
 }
 
-lazy_static! {
-    static ref STATE: Mutex<BotState> = Mutex::new(BotState {});
+// Load a single FAQ file, or every `.md` and `.json` file in a directory
+fn load_faq(path: &Path) -> io::Result<Vec<FaqEntry>> {
+    if !path.is_dir() {
+        return load_faq_file(path);
+    }
+    let mut files: Vec<PathBuf> = std::fs::read_dir(path)?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<io::Result<_>>()?;
+    files.retain(|file| file.extension().map_or(false, |ext| ext == "md" || ext == "json"));
+    files.sort();
+    let mut entries = Vec::new();
+    for file in files {
+        entries.extend(load_faq_file(&file)?);
+    }
+    Ok(entries)
+}
+
+// BM25 parameters: term-frequency saturation and document-length normalisation
+const BM25_K1: f64 = 1.2;
+const BM25_B: f64 = 0.75;
+
+// An inverted index over the FAQ, ranking entries with BM25
+struct FaqIndex {
+    entries: Vec<FaqEntry>,
+    // Term to the entries containing it and the term frequency in each
+    postings: HashMap<String, Vec<(usize, usize)>>,
+    lengths: Vec<usize>,
+    average_length: f64,
+}
+
+// The best FAQ answer for a question
+struct FaqMatch {
+    answer: String,
+    // BM25 score relative to the best score the question's terms could reach, in 0..=1
+    confidence: f64,
+}
+
+impl FaqIndex {
+    fn build(entries: Vec<FaqEntry>) -> FaqIndex {
+        let mut postings: HashMap<String, Vec<(usize, usize)>> = HashMap::new();
+        let mut lengths = Vec::with_capacity(entries.len());
+        for (doc, entry) in entries.iter().enumerate() {
+            // Questions are weighted double by indexing them twice
+            let text = format!("{} {} {}", entry.question, entry.question, entry.answer);
+            let tokens = tokenize(&text);
+            lengths.push(tokens.len());
+            let mut frequencies: HashMap<String, usize> = HashMap::new();
+            for token in tokens {
+                *frequencies.entry(token).or_insert(0) += 1;
+            }
+            for (term, frequency) in frequencies {
+                postings.entry(term).or_default().push((doc, frequency));
+            }
+        }
+        let average_length = lengths.iter().sum::<usize>() as f64 / lengths.len().max(1) as f64;
+        FaqIndex { entries, postings, lengths, average_length }
+    }
+
+    fn idf(&self, term: &str) -> Option<f64> {
+        let containing = self.postings.get(term)?.len() as f64;
+        let total = self.entries.len() as f64;
+        Some(((total - containing + 0.5) / (containing + 0.5) + 1.0).ln())
+    }
+
+    fn search(&self, question: &str) -> Option<FaqMatch> {
+        let mut terms = tokenize(question);
+        terms.sort();
+        terms.dedup();
+        // Terms the corpus has never seen count as the rarest possible term,
+        // so unfamiliar questions end up with a low confidence
+        let rarest = self.idf_of_unique_term();
+        let mut scores = vec![0.0; self.entries.len()];
+        let mut best_possible = 0.0;
+        for term in &terms {
+            let Some(idf) = self.idf(term) else {
+                best_possible += rarest * (BM25_K1 + 1.0);
+                continue;
+            };
+            best_possible += idf * (BM25_K1 + 1.0);
+            for &(doc, frequency) in &self.postings[term] {
+                let tf = frequency as f64;
+                let norm = 1.0 - BM25_B + BM25_B * self.lengths[doc] as f64 / self.average_length;
+                scores[doc] += idf * tf * (BM25_K1 + 1.0) / (tf + BM25_K1 * norm);
+            }
+        }
+        let (doc, &score) = scores
+            .iter()
+            .enumerate()
+            .max_by(|a, b| a.1.partial_cmp(b.1).unwrap_or(std::cmp::Ordering::Equal))?;
+        if score <= 0.0 {
+            return None;
+        }
+        Some(FaqMatch { answer: self.entries[doc].answer.clone(), confidence: (score / best_possible).min(1.0) })
+    }
+
+    fn idf_of_unique_term(&self) -> f64 {
+        let total = self.entries.len() as f64;
+        ((total - 0.5) / 1.5 + 1.0).ln()
+    }
+}
+
+// The FAQ index currently in use and where its corpus lives
+struct FaqStore {
+    path: PathBuf,
+    threshold: f64,
+    index: Mutex<Arc<FaqIndex>>,
+}
+
+impl FaqStore {
+    // A missing corpus gives an empty index, so the bot still starts
+    fn load(path: PathBuf, threshold: f64) -> io::Result<FaqStore> {
+        let entries = if path.exists() { load_faq(&path)? } else { Vec::new() };
+        Ok(FaqStore { path, threshold, index: Mutex::new(Arc::new(FaqIndex::build(entries))) })
+    }
+
+    fn reindex(&self) -> io::Result<usize> {
+        let index = FaqIndex::build(load_faq(&self.path)?);
+        let documents = index.entries.len();
+        *self.index.lock().unwrap() = Arc::new(index);
+        Ok(documents)
+    }
+
+    // The best answer, or `None` when nothing clears the confidence threshold
+    fn answer(&self, question: &str) -> Option<FaqMatch> {
+        let index = self.index.lock().unwrap().clone();
+        index.search(question).filter(|found| found.confidence >= self.threshold)
+    }
+}
+
+// Grants access to the admin endpoints when `X-Admin-Token` matches the configured `admin_token`.
+// Without a configured token every admin request is refused.
+struct AdminToken;
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for AdminToken {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        let configured: Option<String> = request.rocket().figment().extract_inner("admin_token").ok();
+        match (configured, request.headers().get_one("X-Admin-Token")) {
+            (Some(expected), Some(given)) if expected == given => request::Outcome::Success(AdminToken),
+            _ => request::Outcome::Error((Status::Forbidden, ())),
+        }
+    }
+}
+
+// An authenticated support agent. `X-Agent-Token` must match one of the tokens in the
+// `agents` config table, which maps agent names to their tokens.
+struct Agent(String);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Agent {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        let agents: HashMap<String, String> = request.rocket().figment().extract_inner("agents").unwrap_or_default();
+        let token = request.headers().get_one("X-Agent-Token");
+        match agents.into_iter().find(|(_, expected)| Some(expected.as_str()) == token) {
+            Some((name, _)) => request::Outcome::Success(Agent(name)),
+            None => request::Outcome::Error((Status::Forbidden, ())),
+        }
+    }
+}
+
+// The session a request belongs to, taken from the `X-Session-Id` header or the session cookie.
+// Requests without one get a new id, which is also set as a cookie for the next request.
+struct SessionId(String);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for SessionId {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        let existing = request
+            .headers()
+            .get_one(SESSION_HEADER)
+            .map(str::to_string)
+            .or_else(|| request.cookies().get(SESSION_COOKIE).map(|c| c.value().to_string()));
+        let id = existing.unwrap_or_else(|| {
+            let id = uuid::Uuid::new_v4().to_string();
+            request.cookies().add(Cookie::new(SESSION_COOKIE, id.clone()));
+            id
+        });
+        request::Outcome::Success(SessionId(id))
+    }
 }
 
 // Define a request struct for incoming messages
@@ -28,25 +990,714 @@ struct MessageRequest {
 // Define a response struct for bot responses
 #[derive(Serialize)]
 struct BotResponse {
+    session_id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    intent: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    confidence: Option<f64>,
+    // The escalation ticket, once the conversation has been handed to the agents
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ticket: Option<u64>,
+    // Sentiment and urgency of the customer's message
+    score: MessageScore,
     response: String,
 }
 
+// A typing indicator sent by the customer or an agent
+#[derive(Deserialize)]
+struct TypingRequest {
+    typing: bool,
+}
+
+// The `Last-Event-ID` a reconnecting event stream sends, i.e. the last message index it saw
+struct LastEventId(Option<usize>);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for LastEventId {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        let last = request.headers().get_one("Last-Event-ID").and_then(|id| id.parse().ok());
+        request::Outcome::Success(LastEventId(last))
+    }
+}
+
+// A message written by an agent
+#[derive(Deserialize)]
+struct AgentReply {
+    message: String,
+}
+
+#[derive(Serialize)]
+struct ReindexResponse {
+    documents: usize,
+}
+
+// The full transcript of a session
+#[derive(Serialize)]
+struct Transcript {
+    session_id: String,
+    messages: Vec<ChatMessage>,
+}
+
 // Define the bot service
 #[get("/bot?<query>")]
-fn bot_service(query: String, state: &State<Mutex<BotState>>) -> Json<BotResponse> {
-    // Simulate some processing
-    let response = format!("Received message: {}", query);
-    
-    // Error handling and logging could be added here
-    
-    // Return a JSON response
-    Json(BotResponse { response })
+fn bot_service(
+    query: String,
+    session: SessionId,
+    state: &State<Mutex<BotState>>,
+    rules: &State<RuleStore>,
+    faq: &State<FaqStore>,
+) -> Json<BotResponse> {
+    Json(handle_message(&session.0, query, state, rules, faq))
+}
+
+// Answer one customer message and record both sides in the session history.
+// Configured intents take precedence; otherwise the FAQ is searched, and below
+// its confidence threshold the rules' fallback reply is used.
+fn handle_message(
+    session_id: &str,
+    message: String,
+    state: &Mutex<BotState>,
+    rules: &RuleStore,
+    faq: &FaqStore,
+) -> BotResponse {
+    let score = MessageScore::score(&message);
+    let mut state = state.lock().unwrap();
+    state.push(session_id, ChatMessage::from_user(message.clone(), score));
+    let session = state.session(session_id);
+    session.mood = 0.5 * session.mood + 0.5 * score.sentiment;
+    let angry = score.is_angry() || session.mood <= ANGRY_MOOD;
+
+    // Once an agent has claimed the conversation the bot stays out of it,
+    // though an upset customer still pushes the ticket up the queue
+    if let Some(ticket) = state.active_ticket(session_id).filter(|t| t.state == TicketState::Claimed) {
+        let agent = ticket.agent.clone().unwrap_or_default();
+        let id = state.escalate(session_id, score.priority(), "customer is upset", &message);
+        return BotResponse {
+            session_id: session_id.to_string(),
+            intent: Some("handoff".to_string()),
+            confidence: None,
+            ticket: Some(id),
+            score,
+            response: format!("Your message has been passed to {}.", agent),
+        };
+    }
+
+    state.session(session_id).typing(Role::Bot, None, true);
+    let reply = rules.current().respond(&message, &mut state.session(session_id).slots);
+    let (mut reply, confidence) = match reply.intent {
+        Some(_) => (reply, None),
+        None => match faq.answer(&message) {
+            Some(found) => (Reply { intent: Some("faq".to_string()), text: found.answer }, Some(found.confidence)),
+            None => (reply, None),
+        },
+    };
+
+    let lowered = message.to_lowercase();
+    let mut ticket = None;
+    if asks_for_human(&lowered) {
+        let priority = score.priority().max(Priority::Normal);
+        let id = state.escalate(session_id, priority, "customer asked for a human", &message);
+        reply = Reply {
+            intent: Some("handoff".to_string()),
+            text: format!("I'm connecting you with a support agent (ticket #{}).", id),
+        };
+        ticket = Some(id);
+    } else if angry {
+        let already_escalated = state.active_ticket(session_id).is_some();
+        let id = state.escalate(session_id, score.priority().max(Priority::High), "customer is upset", &message);
+        if !already_escalated {
+            reply.text = format!("{} I'm sorry for the trouble, a support agent will step in (ticket #{}).", reply.text, id);
+        }
+        ticket = Some(id);
+    } else if reply.intent.is_none() {
+        let id = state.escalate(session_id, score.priority(), "bot could not answer", &message);
+        reply.text = format!("{} A support agent will follow up (ticket #{}).", reply.text, id);
+        ticket = Some(id);
+    }
+    state.session(session_id).typing(Role::Bot, None, false);
+    state.push(session_id, ChatMessage::from_bot(reply.text.clone(), reply.intent.clone(), ticket));
+
+    BotResponse {
+        session_id: session_id.to_string(),
+        intent: reply.intent,
+        confidence,
+        ticket,
+        score,
+        response: reply.text,
+    }
+}
+
+// Send a message over the chat channel; the reply is pushed to listeners as well as returned
+#[post("/bot/messages", format = "json", data = "<request>")]
+fn post_message(
+    request: Json<MessageRequest>,
+    session: SessionId,
+    state: &State<Mutex<BotState>>,
+    rules: &State<RuleStore>,
+    faq: &State<FaqStore>,
+) -> Json<BotResponse> {
+    Json(handle_message(&session.0, request.into_inner().message, state, rules, faq))
+}
+
+// Stream the messages and typing indicators of an existing session. Messages after `last_seen`
+// are replayed first, so a client reconnecting with its session id picks up where it left off.
+// A listener that falls behind the channel gets the messages it missed from the transcript.
+fn session_stream<'a>(
+    state: &'a Mutex<BotState>,
+    session_id: &str,
+    last_seen: Option<usize>,
+    mut shutdown: Shutdown,
+) -> Result<EventStream![Event + 'a], Status> {
+    let (backlog, mut next, mut events) = {
+        let mut state = state.lock().unwrap();
+        state.expire_idle();
+        let session = state.sessions.get_mut(session_id).ok_or(Status::NotFound)?;
+        session.last_active = Instant::now();
+        let backlog = session.replay(last_seen.map_or(0, |index| index + 1));
+        (backlog, session.messages.len(), session.events.subscribe())
+    };
+    let session_id = session_id.to_string();
+    Ok(EventStream! {
+        for event in backlog {
+            yield event.to_sse();
+        }
+        loop {
+            let received = select! {
+                event = events.recv() => event,
+                _ = &mut shutdown => break,
+            };
+            let events = match received {
+                Ok(event) => vec![event],
+                Err(broadcast::error::RecvError::Lagged(_)) => {
+                    let state = state.lock().unwrap();
+                    state.sessions.get(&session_id).map(|session| session.replay(next)).unwrap_or_default()
+                }
+                Err(broadcast::error::RecvError::Closed) => break,
+            };
+            for event in events {
+                // Messages already sent from the transcript come through the channel again
+                if let ChannelEvent::Message { index, .. } = &event {
+                    if *index < next {
+                        continue;
+                    }
+                    next = index + 1;
+                }
+                yield event.to_sse();
+            }
+        }
+    })
+}
+
+// The customer's side of the chat channel. Browsers' `EventSource` cannot set headers,
+// so the session id may also be given as the `session` query parameter.
+#[get("/bot/events?<session>")]
+fn customer_events<'a>(
+    session: Option<String>,
+    session_id: SessionId,
+    last_event: LastEventId,
+    state: &'a State<Mutex<BotState>>,
+    shutdown: Shutdown,
+) -> Result<EventStream![Event + 'a], Status> {
+    let id = session.unwrap_or(session_id.0);
+    session_stream(state, &id, last_event.0, shutdown)
+}
+
+#[post("/bot/typing", format = "json", data = "<request>")]
+fn customer_typing(request: Json<TypingRequest>, session: SessionId, state: &State<Mutex<BotState>>) -> Status {
+    let mut state = state.lock().unwrap();
+    state.session(&session.0).typing(Role::User, None, request.typing);
+    Status::NoContent
+}
+
+// Return the transcript of a session that has not expired yet
+#[get("/bot/session/<id>")]
+fn session_transcript(id: String, state: &State<Mutex<BotState>>) -> Result<Json<Transcript>, Status> {
+    let mut state = state.lock().unwrap();
+    state.expire_idle();
+    let session = state.sessions.get(&id).ok_or(Status::NotFound)?;
+    Ok(Json(Transcript { messages: session.messages.clone(), session_id: id }))
+}
+
+// Rebuild the FAQ index from the corpus on disk
+#[post("/bot/admin/reindex")]
+fn reindex_faq(_admin: AdminToken, faq: &State<FaqStore>) -> Result<Json<ReindexResponse>, Status> {
+    let documents = faq.reindex().map_err(|e| {
+        eprintln!("Failed to reindex the FAQ: {}", e);
+        Status::InternalServerError
+    })?;
+    Ok(Json(ReindexResponse { documents }))
+}
+
+// Parse a query bound given as Unix seconds, an RFC 3339 timestamp or a `YYYY-MM-DD` date (UTC midnight)
+fn parse_time(value: &str) -> Result<u64, Status> {
+    if let Ok(secs) = value.parse::<u64>() {
+        return Ok(secs);
+    }
+    if let Ok(time) = chrono::DateTime::parse_from_rfc3339(value) {
+        return u64::try_from(time.timestamp()).map_err(|_| Status::BadRequest);
+    }
+    let date = chrono::NaiveDate::parse_from_str(value, "%Y-%m-%d").map_err(|_| Status::BadRequest)?;
+    let midnight = date.and_hms_opt(0, 0, 0).ok_or(Status::BadRequest)?;
+    u64::try_from(midnight.and_utc().timestamp()).map_err(|_| Status::BadRequest)
+}
+
+fn parse_range(from: Option<&str>, to: Option<&str>) -> Result<(Option<u64>, Option<u64>), Status> {
+    Ok((from.map(parse_time).transpose()?, to.map(parse_time).transpose()?))
+}
+
+// A question the bot could not answer and how often it was asked
+#[derive(Serialize)]
+struct UnansweredQuestion {
+    question: String,
+    count: usize,
+}
+
+// Conversation metrics over a time range
+#[derive(Serialize)]
+struct Analytics {
+    conversations: usize,
+    bot_replies: usize,
+    intent_hits: HashMap<String, usize>,
+    // Share of bot replies that fell back because nothing matched
+    fallback_rate: f64,
+    // Share of conversations handed to an agent: on request, after a fallback or because the
+    // customer was upset, as recorded by the ticket on the bot's replies
+    escalation_rate: f64,
+    // Customer messages per conversation, over conversations the bot resolved without an agent
+    average_turns_to_resolution: f64,
+    top_unanswered: Vec<UnansweredQuestion>,
+}
+
+fn ratio(part: usize, whole: usize) -> f64 {
+    if whole == 0 {
+        0.0
+    } else {
+        part as f64 / whole as f64
+    }
+}
+
+impl Analytics {
+    fn compute<'a>(lines: impl Iterator<Item = &'a LoggedMessage>, top: usize) -> Analytics {
+        #[derive(Default)]
+        struct Conversation {
+            user_turns: usize,
+            escalated: bool,
+        }
+
+        let mut conversations: HashMap<&str, Conversation> = HashMap::new();
+        let mut intent_hits: HashMap<String, usize> = HashMap::new();
+        let mut unanswered: HashMap<String, usize> = HashMap::new();
+        let mut bot_replies = 0;
+        let mut fallbacks = 0;
+        // The customer message each bot reply answers, per conversation
+        let mut last_question: HashMap<&str, &str> = HashMap::new();
+        for line in lines {
+            let conversation = conversations.entry(line.session_id.as_str()).or_default();
+            match line.message.role {
+                Role::User => {
+                    conversation.user_turns += 1;
+                    last_question.insert(line.session_id.as_str(), line.message.text.as_str());
+                }
+                Role::Bot => {
+                    bot_replies += 1;
+                    conversation.escalated |= line.message.ticket.is_some();
+                    match line.message.intent.as_deref() {
+                        Some(intent) => *intent_hits.entry(intent.to_string()).or_insert(0) += 1,
+                        None => {
+                            fallbacks += 1;
+                            if let Some(question) = last_question.get(line.session_id.as_str()) {
+                                *unanswered.entry(question.trim().to_lowercase()).or_insert(0) += 1;
+                            }
+                        }
+                    }
+                }
+                Role::Agent => conversation.escalated = true,
+            }
+        }
+
+        let escalated = conversations.values().filter(|c| c.escalated).count();
+        let resolved: Vec<usize> =
+            conversations.values().filter(|c| !c.escalated && c.user_turns > 0).map(|c| c.user_turns).collect();
+        let mut top_unanswered: Vec<UnansweredQuestion> =
+            unanswered.into_iter().map(|(question, count)| UnansweredQuestion { question, count }).collect();
+        top_unanswered.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.question.cmp(&b.question)));
+        top_unanswered.truncate(top);
+
+        Analytics {
+            conversations: conversations.len(),
+            bot_replies,
+            intent_hits,
+            fallback_rate: ratio(fallbacks, bot_replies),
+            escalation_rate: ratio(escalated, conversations.len()),
+            average_turns_to_resolution: ratio(resolved.iter().sum(), resolved.len()),
+            top_unanswered,
+        }
+    }
+}
+
+#[get("/bot/admin/analytics?<from>&<to>&<top>")]
+async fn analytics(
+    _admin: AdminToken,
+    from: Option<&str>,
+    to: Option<&str>,
+    top: Option<usize>,
+    state: &State<Mutex<BotState>>,
+) -> Result<Json<Analytics>, Status> {
+    let lines = load_transcripts(from, to, state).await?;
+    Ok(Json(Analytics::compute(lines.iter(), top.unwrap_or(10))))
+}
+
+// Read transcript lines without holding the state lock, so chat traffic is not blocked
+async fn load_transcripts(
+    from: Option<&str>,
+    to: Option<&str>,
+    state: &State<Mutex<BotState>>,
+) -> Result<Vec<LoggedMessage>, Status> {
+    let (from, to) = parse_range(from, to)?;
+    let snapshot = state.lock().unwrap().log.snapshot(from, to);
+    tokio::task::spawn_blocking(move || snapshot.load())
+        .await
+        .map_err(|_| Status::InternalServerError)?
+        .map_err(|e| {
+            eprintln!("Failed to read the transcript log: {}", e);
+            Status::InternalServerError
+        })
+}
+
+// Export transcript lines in a time range as JSON Lines (the default) or CSV
+#[get("/bot/admin/transcripts?<from>&<to>&<format>")]
+async fn export_transcripts(
+    _admin: AdminToken,
+    from: Option<&str>,
+    to: Option<&str>,
+    format: Option<&str>,
+    state: &State<Mutex<BotState>>,
+) -> Result<(ContentType, String), Status> {
+    let lines = load_transcripts(from, to, state).await?;
+    match format.unwrap_or("jsonl") {
+        "jsonl" => {
+            let mut body = String::new();
+            for line in &lines {
+                body.push_str(&serde_json::to_string(line).map_err(|_| Status::InternalServerError)?);
+                body.push('\n');
+            }
+            Ok((ContentType::new("application", "x-ndjson"), body))
+        }
+        "csv" => {
+            let mut writer = csv::Writer::from_writer(Vec::new());
+            let csv_error = |_| Status::InternalServerError;
+            writer.write_record(["session_id", "timestamp", "role", "author", "intent", "text"]).map_err(csv_error)?;
+            for line in &lines {
+                let message = &line.message;
+                writer
+                    .write_record([
+                        line.session_id.as_str(),
+                        &message.timestamp.to_string(),
+                        message.role.name(),
+                        message.author.as_deref().unwrap_or_default(),
+                        message.intent.as_deref().unwrap_or_default(),
+                        message.text.as_str(),
+                    ])
+                    .map_err(csv_error)?;
+            }
+            let body = writer.into_inner().map_err(|_| Status::InternalServerError)?;
+            Ok((ContentType::CSV, String::from_utf8(body).map_err(|_| Status::InternalServerError)?))
+        }
+        _ => Err(Status::BadRequest),
+    }
+}
+
+// The escalation queue: unclosed tickets, highest priority and oldest first
+#[get("/agent/tickets")]
+fn ticket_queue(_agent: Agent, state: &State<Mutex<BotState>>) -> Json<Vec<Ticket>> {
+    let state = state.lock().unwrap();
+    let mut queue: Vec<Ticket> = state.tickets.values().filter(|t| t.is_active()).cloned().collect();
+    queue.sort_by(|a, b| b.priority.cmp(&a.priority).then(a.created_at.cmp(&b.created_at)).then(a.id.cmp(&b.id)));
+    Json(queue)
+}
+
+// Take ownership of an open ticket
+#[post("/agent/tickets/<id>/claim")]
+fn claim_ticket(id: u64, agent: Agent, state: &State<Mutex<BotState>>) -> Result<Json<Ticket>, Status> {
+    let mut state = state.lock().unwrap();
+    let ticket = state.tickets.get_mut(&id).ok_or(Status::NotFound)?;
+    match (&ticket.state, &ticket.agent) {
+        (TicketState::Open, _) => {}
+        (TicketState::Claimed, Some(owner)) if *owner == agent.0 => {}
+        _ => return Err(Status::Conflict),
+    }
+    ticket.state = TicketState::Claimed;
+    ticket.agent = Some(agent.0);
+    Ok(Json(ticket.clone()))
+}
+
+// Post an agent's message into the customer's session
+#[post("/agent/tickets/<id>/reply", format = "json", data = "<reply>")]
+fn reply_ticket(
+    id: u64,
+    agent: Agent,
+    reply: Json<AgentReply>,
+    state: &State<Mutex<BotState>>,
+) -> Result<Json<Ticket>, Status> {
+    let mut state = state.lock().unwrap();
+    let ticket = state.tickets.get(&id).ok_or(Status::NotFound)?.clone();
+    if ticket.state != TicketState::Claimed || ticket.agent.as_deref() != Some(agent.0.as_str()) {
+        return Err(Status::Conflict);
+    }
+    if !state.sessions.contains_key(&ticket.session_id) {
+        return Err(Status::NotFound);
+    }
+    state.push(&ticket.session_id, ChatMessage::from_agent(&agent.0, reply.into_inner().message));
+    Ok(Json(ticket))
+}
+
+// Close a ticket and hand the conversation back to the bot
+#[post("/agent/tickets/<id>/close")]
+fn close_ticket(id: u64, agent: Agent, state: &State<Mutex<BotState>>) -> Result<Json<Ticket>, Status> {
+    let mut state = state.lock().unwrap();
+    let ticket = state.tickets.get_mut(&id).ok_or(Status::NotFound)?;
+    match (&ticket.state, &ticket.agent) {
+        (TicketState::Open, _) => {}
+        (TicketState::Claimed, Some(owner)) if *owner == agent.0 => {}
+        _ => return Err(Status::Conflict),
+    }
+    ticket.state = TicketState::Closed;
+    ticket.agent.get_or_insert(agent.0);
+    let ticket = ticket.clone();
+    if let Some(session) = state.sessions.get_mut(&ticket.session_id) {
+        session.ticket = None;
+    }
+    Ok(Json(ticket))
+}
+
+// The agent's side of a ticket's chat channel
+#[get("/agent/tickets/<id>/events")]
+fn agent_events<'a>(
+    id: u64,
+    _agent: Agent,
+    last_event: LastEventId,
+    state: &'a State<Mutex<BotState>>,
+    shutdown: Shutdown,
+) -> Result<EventStream![Event + 'a], Status> {
+    let session_id = state.lock().unwrap().tickets.get(&id).ok_or(Status::NotFound)?.session_id.clone();
+    session_stream(state, &session_id, last_event.0, shutdown)
+}
+
+#[post("/agent/tickets/<id>/typing", format = "json", data = "<request>")]
+fn agent_typing(
+    id: u64,
+    agent: Agent,
+    request: Json<TypingRequest>,
+    state: &State<Mutex<BotState>>,
+) -> Result<Status, Status> {
+    let state = state.lock().unwrap();
+    let ticket = state.tickets.get(&id).ok_or(Status::NotFound)?;
+    let session = state.sessions.get(&ticket.session_id).ok_or(Status::NotFound)?;
+    session.typing(Role::Agent, Some(agent.0), request.typing);
+    Ok(Status::NoContent)
 }
 
 // Set up the Rocket launch
 #[launch]
 fn rocket() -> _ {
-    rocket::build()
-        .mount("/", routes![bot_service])
-        .manage(STATE.clone())
+    let rocket = rocket::build();
+    let idle_secs: u64 = rocket.figment().extract_inner("session_idle_secs").unwrap_or(30 * 60);
+    let rules_path: PathBuf = rocket.figment().extract_inner("rules_path").unwrap_or_else(|_| "bot_rules.yaml".into());
+    let rules = RuleStore::load(rules_path).expect("Failed to load the bot rules");
+    let faq_path: PathBuf = rocket.figment().extract_inner("faq_path").unwrap_or_else(|_| "faq".into());
+    let faq_threshold: f64 = rocket.figment().extract_inner("faq_threshold").unwrap_or(0.3);
+    let faq = FaqStore::load(faq_path, faq_threshold).expect("Failed to load the FAQ corpus");
+    let log_path: Option<PathBuf> = rocket.figment().extract_inner("transcript_log").ok();
+    let log = TranscriptLog::open(log_path.as_deref()).expect("Failed to open the transcript log");
+    rocket
+        .mount("/", routes![
+            bot_service,
+            post_message,
+            customer_events,
+            customer_typing,
+            session_transcript,
+            reindex_faq,
+            analytics,
+            export_transcripts,
+            ticket_queue,
+            claim_ticket,
+            reply_ticket,
+            close_ticket,
+            agent_events,
+            agent_typing,
+        ])
+        .manage(faq)
+        .manage(Mutex::new(BotState::new(Duration::from_secs(idle_secs), log)))
+        .manage(rules)
+        .attach(AdHoc::on_liftoff("Rule reloader", |rocket| {
+            Box::pin(async move {
+                // Poll the rules file so support staff can edit it without a redeploy
+                let rules = rocket.state::<RuleStore>().expect("rules are managed").clone();
+                tokio::spawn(async move {
+                    let mut interval = tokio::time::interval(Duration::from_secs(2));
+                    loop {
+                        interval.tick().await;
+                        match rules.reload_if_changed() {
+                            Ok(true) => eprintln!("Reloaded bot rules from {}", rules.path.display()),
+                            Ok(false) => {}
+                            Err(e) => eprintln!("Keeping previous bot rules: {}", e),
+                        }
+                    }
+                });
+            })
+        }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn faq_index() -> FaqIndex {
+        let entry = |question: &str, answer: &str| FaqEntry { question: question.to_string(), answer: answer.to_string() };
+        FaqIndex::build(vec![
+            entry("How do I reset my password?", "Use the reset link on the login page."),
+            entry("What are your opening hours?", "We are open from 9 to 5 on weekdays."),
+            entry("How do I request a refund?", "Refunds are paid within 14 days."),
+            entry("如何申请退款？", "请在订单页面点击退款。"),
+        ])
+    }
+
+    #[test]
+    fn test_fill_template_substitutes_in_a_single_pass() {
+        let slots = HashMap::from([("order".to_string(), "{message}".to_string())]);
+        let filled = fill_template("Order {order} for {message}: {unknown} {", "hi", &slots);
+        assert_eq!(filled, "Order {message} for hi: {unknown} {");
+        assert_eq!(fill_template("no placeholders", "hi", &slots), "no placeholders");
+    }
+
+    #[test]
+    fn test_tokenize_splits_words_and_cjk_bigrams() {
+        assert_eq!(tokenize("Hello, World 42"), ["hello", "world", "42"]);
+        assert_eq!(tokenize("退款流程"), ["退", "款", "流", "程", "退款", "款流", "流程"]);
+        assert_eq!(tokenize("refund退款"), ["refund", "退", "款", "退款"]);
+    }
+
+    #[test]
+    fn test_faq_search_ranks_the_matching_entry_first() {
+        let index = faq_index();
+        let found = index.search("how can I reset my password").expect("a match");
+        assert!(found.answer.starts_with("Use the reset link"));
+        assert!(found.confidence > 0.5, "{}", found.confidence);
+        assert!(index.search("refund").unwrap().answer.starts_with("Refunds"));
+        assert!(index.search("怎么退款").unwrap().answer.contains("点击退款"));
+        assert!(index.search("quantum flux").is_none());
+    }
+
+    #[test]
+    fn test_faq_answers_below_the_threshold_are_dropped() {
+        let store = FaqStore { path: PathBuf::new(), threshold: 0.3, index: Mutex::new(Arc::new(faq_index())) };
+        let question = "password for the quantum flux capacitor banana spaceship";
+        let weak = store.index.lock().unwrap().search(question).expect("one term matches");
+        assert!(weak.confidence < 0.3, "{}", weak.confidence);
+        assert!(store.answer(question).is_none());
+        assert!(store.answer("reset my password").is_some());
+    }
+
+    #[test]
+    fn test_message_score() {
+        assert!(MessageScore::score("Thanks, this is great").sentiment > 0.0);
+        assert!(MessageScore::score("not bad at all").sentiment > 0.0);
+
+        let angry = MessageScore::score("This is terrible, worst service ever!!");
+        assert!(angry.is_angry());
+        assert_eq!(angry.priority(), Priority::High);
+
+        let urgent = MessageScore::score("urgent, please help now!!!");
+        assert!(urgent.is_urgent() && !urgent.is_angry());
+
+        assert!(MessageScore::score("我非常生气").is_angry());
+        assert!(MessageScore::score("我不满意").sentiment < 0.0);
+        let neutral = MessageScore::score("where is my parcel");
+        assert_eq!((neutral.sentiment, neutral.urgency), (0.0, 0.0));
+    }
+
+    #[test]
+    fn test_asks_for_human_matches_whole_words() {
+        assert!(asks_for_human("can i talk to a real person?"));
+        assert!(asks_for_human("agent please"));
+        assert!(asks_for_human("我要人工客服"));
+        assert!(!asks_for_human("a question about account management"));
+        assert!(!asks_for_human("is this person real"));
+    }
+
+    #[test]
+    fn test_intent_keywords_match_whole_words() {
+        let intent = Intent::compile(IntentConfig {
+            name: "refund".to_string(),
+            priority: 0,
+            keywords: vec!["Refund".to_string(), "money back".to_string(), "退款".to_string()],
+            patterns: vec![r"#\d{5}".to_string()],
+            slots: HashMap::new(),
+            response: "ok".to_string(),
+        })
+        .unwrap();
+        assert_eq!(intent.score("I want a REFUND for #12345"), 2);
+        assert_eq!(intent.score("can I get my money back?"), 1);
+        assert_eq!(intent.score("我要退款"), 1);
+        assert_eq!(intent.score("refunded already, no money"), 0);
+    }
+
+    #[test]
+    fn test_a_broken_rules_file_is_reported_once() {
+        let path = std::env::temp_dir().join(format!("bot-rules-{}.yaml", std::process::id()));
+        std::fs::write(&path, "intents:\n  - name: hello\n    keywords: [hello]\n    response: Hi!\n").unwrap();
+        let store = RuleStore::load(path.clone()).unwrap();
+
+        std::fs::write(&path, "intents: [").unwrap();
+        let later = SystemTime::now() + Duration::from_secs(10);
+        std::fs::File::options().write(true).open(&path).unwrap().set_modified(later).unwrap();
+        let first = store.reload_if_changed();
+        let second = store.reload_if_changed();
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(first.is_err());
+        assert!(matches!(second, Ok(false)));
+        assert_eq!(store.current().respond("hello", &mut HashMap::new()).intent.as_deref(), Some("hello"));
+    }
+
+    #[test]
+    fn test_analytics_over_a_fixed_transcript() {
+        let line = |session: &str, message: ChatMessage| LoggedMessage { session_id: session.to_string(), message };
+        let user = |text: &str| ChatMessage::from_user(text.to_string(), MessageScore::score(text));
+        let bot = |intent: Option<&str>, ticket: Option<u64>| {
+            ChatMessage::from_bot("reply".to_string(), intent.map(str::to_string), ticket)
+        };
+        let lines = vec![
+            // Resolved by the bot in two turns
+            line("a", user("where is my order")),
+            line("a", bot(Some("order_status"), None)),
+            line("a", user("thanks")),
+            line("a", bot(Some("thanks"), None)),
+            // Fell back and escalated
+            line("b", user("Blah")),
+            line("b", bot(None, Some(1))),
+            // Answered, but escalated because the customer was upset
+            line("c", user("this is terrible!!")),
+            line("c", bot(Some("faq"), Some(2))),
+            line("c", ChatMessage::from_agent("alice", "sorry about that".to_string())),
+            // Fell back on the same question
+            line("d", user("blah ")),
+            line("d", bot(None, Some(3))),
+            line("d", user("hm")),
+            line("d", bot(None, Some(3))),
+        ];
+        let analytics = Analytics::compute(lines.iter(), 1);
+
+        assert_eq!(analytics.conversations, 4);
+        assert_eq!(analytics.bot_replies, 6);
+        assert_eq!(analytics.intent_hits.len(), 3);
+        assert_eq!(analytics.intent_hits["faq"], 1);
+        assert!((analytics.fallback_rate - 0.5).abs() < 1e-12);
+        assert!((analytics.escalation_rate - 0.75).abs() < 1e-12);
+        assert!((analytics.average_turns_to_resolution - 2.0).abs() < 1e-12);
+        assert_eq!(analytics.top_unanswered.len(), 1);
+        assert_eq!((analytics.top_unanswered[0].question.as_str(), analytics.top_unanswered[0].count), ("blah", 2));
+    }
 }
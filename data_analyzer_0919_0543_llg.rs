@@ -1,4 +1,4 @@
-use rocket::get;
+use rocket::{delete, get, post};
 use rocket::serde::json::Json;
 use rocket::serde::{Serialize, Deserialize};
 use rocket::response::status;
@@ -7,6 +7,22 @@ use std::collections::HashMap;
 use std::error::Error;
 use std::fmt;
 use std::io::{self, BufRead};
+use std::path::{Path, PathBuf};
+
+// 直方图中的一个区间，lower 包含在内，upper 仅在最后一个区间包含
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct HistogramBin {
+    pub lower: f64,
+    pub upper: f64,
+    pub count: usize,
+}
+
+// 某个百分位对应的值
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PercentileValue {
+    pub percentile: f64,
+    pub value: f64,
+}
 
 // 定义一个结构体来存储分析结果
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -15,6 +31,392 @@ pub struct AnalysisResult {
     pub mean: f64,
     pub median: f64,
     pub mode: f64,
+    // 样本方差与标准差（除以 n - 1）
+    pub variance: f64,
+    pub std_dev: f64,
+    pub min: f64,
+    pub max: f64,
+    pub range: f64,
+    pub q1: f64,
+    pub q3: f64,
+    pub iqr: f64,
+    // 偏度与超额峰度（正态分布均为 0）
+    pub skewness: f64,
+    pub kurtosis: f64,
+    pub percentiles: Vec<PercentileValue>,
+    pub histogram: Vec<HistogramBin>,
+}
+
+// 分析选项：直方图的区间数和需要额外计算的百分位
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AnalysisOptions {
+    pub bins: usize,
+    pub percentiles: Vec<f64>,
+}
+
+impl Default for AnalysisOptions {
+    fn default() -> Self {
+        AnalysisOptions { bins: 10, percentiles: vec![5.0, 25.0, 50.0, 75.0, 95.0] }
+    }
+}
+
+// 直方图区间数上限，避免超大的 bins 分配巨大的直方图
+const MAX_BINS: usize = 10_000;
+
+impl AnalysisOptions {
+    // 从查询参数构造选项，百分位以逗号分隔，例如 "5,95,99.9"
+    pub fn from_query(bins: Option<usize>, percentiles: Option<&str>) -> Result<AnalysisOptions, AnalysisError> {
+        let mut options = AnalysisOptions::default();
+        if let Some(bins) = bins {
+            if bins == 0 {
+                return Err(AnalysisError::new("bins must be at least 1"));
+            }
+            if bins > MAX_BINS {
+                return Err(AnalysisError::new(&format!("bins must be at most {}", MAX_BINS)));
+            }
+            options.bins = bins;
+        }
+        if let Some(percentiles) = percentiles {
+            options.percentiles = percentiles
+                .split(',')
+                .map(|p| p.trim().parse::<f64>().ok().filter(|p| (0.0..=100.0).contains(p)))
+                .collect::<Option<Vec<_>>>()
+                .ok_or_else(|| AnalysisError::new("percentiles must be numbers between 0 and 100"))?;
+        }
+        Ok(options)
+    }
+}
+
+impl AnalysisResult {
+    // 对一组数值计算完整的描述统计，内部会先排序
+    pub fn from_values(mut data: Vec<f64>, options: &AnalysisOptions) -> Result<AnalysisResult, AnalysisError> {
+        if data.is_empty() {
+            return Err(AnalysisError::new("No data to analyze"));
+        }
+        if data.iter().any(|v| !v.is_finite()) {
+            return Err(AnalysisError::new("Data contains NaN or infinite values"));
+        }
+        data.sort_by(|a, b| a.partial_cmp(b).expect("finite values are ordered"));
+
+        let mean = statistics::mean(&data);
+        let variance = statistics::variance(&data, mean);
+        let min = data[0];
+        let max = data[data.len() - 1];
+        let q1 = statistics::percentile(&data, 25.0);
+        let q3 = statistics::percentile(&data, 75.0);
+        Ok(AnalysisResult {
+            total_count: data.len(),
+            mean,
+            median: statistics::median(&data),
+            mode: statistics::mode(&data),
+            variance,
+            std_dev: variance.sqrt(),
+            min,
+            max,
+            range: max - min,
+            q1,
+            q3,
+            iqr: q3 - q1,
+            skewness: statistics::skewness(&data, mean),
+            kurtosis: statistics::kurtosis(&data, mean),
+            percentiles: options
+                .percentiles
+                .iter()
+                .map(|&percentile| PercentileValue { percentile, value: statistics::percentile(&data, percentile) })
+                .collect(),
+            histogram: statistics::histogram(&data, options.bins),
+        })
+    }
+}
+
+// 多列数据中推断出的列类型
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ColumnType {
+    Numeric,
+    Categorical,
+    Datetime,
+    Boolean,
+}
+
+// 某个取值及其出现次数
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ValueCount {
+    pub value: String,
+    pub count: usize,
+}
+
+// 分类列的统计：不同取值的个数与出现最多的前 k 个取值
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CategoricalSummary {
+    pub cardinality: usize,
+    pub top_values: Vec<ValueCount>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BooleanSummary {
+    pub true_count: usize,
+    pub false_count: usize,
+}
+
+// 时间列的统计，时间以 ISO 8601 格式返回
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct DatetimeSummary {
+    pub min: String,
+    pub max: String,
+}
+
+// 单列的分析结果，只有与列类型对应的那一项统计存在
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ColumnAnalysis {
+    pub name: String,
+    pub column_type: ColumnType,
+    pub count: usize,
+    pub null_count: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub numeric: Option<AnalysisResult>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub categorical: Option<CategoricalSummary>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub boolean: Option<BooleanSummary>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub datetime: Option<DatetimeSummary>,
+}
+
+// 多列数据的分析结果
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TableAnalysis {
+    pub row_count: usize,
+    pub has_header: bool,
+    pub columns: Vec<ColumnAnalysis>,
+}
+
+// 流式分析中由 t-digest 估算的百分位，rank_error 为以百分位点表示的排名误差上界
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ApproximatePercentile {
+    pub percentile: f64,
+    pub value: f64,
+    pub rank_error: f64,
+}
+
+// 流式分析的误差说明：均值、方差、最值由 Welford 算法精确计算，
+// 百分位与不同值个数为近似值
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ErrorBounds {
+    // 所有百分位中最大的排名误差（百分位点）
+    pub percentile_rank_error: f64,
+    // HyperLogLog 的相对标准误差
+    pub distinct_count_relative_error: f64,
+    // 约 95% 置信度的不同值个数区间
+    pub distinct_count_low: u64,
+    pub distinct_count_high: u64,
+}
+
+// 流式分析结果，内存占用与文件大小无关
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct StreamingAnalysis {
+    pub total_count: u64,
+    pub null_count: u64,
+    pub mean: f64,
+    pub variance: f64,
+    pub std_dev: f64,
+    pub min: f64,
+    pub max: f64,
+    pub median: f64,
+    pub percentiles: Vec<ApproximatePercentile>,
+    pub distinct_count: u64,
+    pub error_bounds: ErrorBounds,
+}
+
+// 数值列之间的相关系数矩阵，方差为 0 的列相关系数为 null
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CorrelationMatrix {
+    pub columns: Vec<String>,
+    // 参与计算的行数：所有数值列都不为空的行
+    pub row_count: usize,
+    pub pearson: Vec<Vec<Option<f64>>>,
+    pub spearman: Vec<Vec<Option<f64>>>,
+}
+
+// 回归系数及其显著性检验（双侧 t 检验）
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Coefficient {
+    pub name: String,
+    pub estimate: f64,
+    pub std_error: f64,
+    // 完全拟合时标准误为 0，t 值无定义（为 null），p 值按估计值是否为 0 取 1 或 0
+    pub t_value: Option<f64>,
+    pub p_value: f64,
+}
+
+// 残差的分布与残差标准误
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ResidualStats {
+    pub min: f64,
+    pub q1: f64,
+    pub median: f64,
+    pub q3: f64,
+    pub max: f64,
+    pub standard_error: f64,
+}
+
+// 普通最小二乘线性回归的结果，第一个系数为截距
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RegressionResult {
+    pub target: String,
+    pub row_count: usize,
+    pub degrees_of_freedom: usize,
+    pub coefficients: Vec<Coefficient>,
+    pub r_squared: f64,
+    pub adjusted_r_squared: f64,
+    pub residuals: ResidualStats,
+}
+
+// 离群值检测方法
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum OutlierMethod {
+    ZScore,
+    ModifiedZScore,
+    Iqr,
+    IsolationForest,
+}
+
+impl OutlierMethod {
+    pub fn from_name(name: &str) -> Option<OutlierMethod> {
+        match name {
+            "zscore" | "z_score" => Some(OutlierMethod::ZScore),
+            "mad" | "modified_z_score" => Some(OutlierMethod::ModifiedZScore),
+            "iqr" => Some(OutlierMethod::Iqr),
+            "isolation_forest" => Some(OutlierMethod::IsolationForest),
+            _ => None,
+        }
+    }
+
+    // 各方法的常用阈值：|z| > 3，|修正 z| > 3.5，超出四分位 1.5 倍 IQR，异常分数 > 0.6
+    pub fn default_threshold(self) -> f64 {
+        match self {
+            OutlierMethod::ZScore => 3.0,
+            OutlierMethod::ModifiedZScore => 3.5,
+            OutlierMethod::Iqr => 1.5,
+            OutlierMethod::IsolationForest => 0.6,
+        }
+    }
+}
+
+// 一个离群值：所在行号（从 1 开始）、值以及该方法下的分数
+// 数据的分散度（标准差、MAD 或 IQR）为 0 时，偏离中心的值分数为无穷大，在 JSON 中为 null
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Outlier {
+    pub row: usize,
+    pub value: f64,
+    pub score: f64,
+}
+
+// 离群值检测结果，孤立森林没有固定的上下界
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct OutlierReport {
+    pub method: OutlierMethod,
+    pub threshold: f64,
+    pub total_count: usize,
+    pub outlier_count: usize,
+    pub lower_fence: Option<f64>,
+    pub upper_fence: Option<f64>,
+    pub outliers: Vec<Outlier>,
+}
+
+// 重采样时空区间的填充方式
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum FillStrategy {
+    Zero,
+    Forward,
+    Backward,
+    Linear,
+    Mean,
+}
+
+impl FillStrategy {
+    pub fn from_name(name: &str) -> Option<FillStrategy> {
+        match name {
+            "zero" => Some(FillStrategy::Zero),
+            "forward" | "ffill" => Some(FillStrategy::Forward),
+            "backward" | "bfill" => Some(FillStrategy::Backward),
+            "linear" => Some(FillStrategy::Linear),
+            "mean" => Some(FillStrategy::Mean),
+            _ => None,
+        }
+    }
+}
+
+// 重采样后的一个时间点；observed 为 false 表示该区间没有原始数据，值由填充得到。
+// 窗口尚未填满时 sma 和 wma 为 null
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TimeSeriesPoint {
+    pub timestamp: String,
+    pub value: f64,
+    pub observed: bool,
+    pub sma: Option<f64>,
+    pub ema: f64,
+    pub wma: Option<f64>,
+}
+
+// STL 风格的分解结果，各分量与重采样后的时间点一一对应
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Decomposition {
+    pub period: usize,
+    pub trend: Vec<f64>,
+    pub seasonal: Vec<f64>,
+    pub residual: Vec<f64>,
+    // 趋势与季节性强度，取值 0 到 1，越大越明显
+    pub trend_strength: f64,
+    pub seasonal_strength: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Autocorrelation {
+    pub lag: usize,
+    pub value: f64,
+}
+
+// 时间序列分析结果
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TimeSeriesAnalysis {
+    pub time_column: String,
+    pub value_column: String,
+    pub interval_secs: i64,
+    pub fill: FillStrategy,
+    pub observed_count: usize,
+    pub filled_count: usize,
+    pub points: Vec<TimeSeriesPoint>,
+    pub decomposition: Option<Decomposition>,
+    pub autocorrelation: Vec<Autocorrelation>,
+    // 白噪声假设下自相关系数的 95% 置信界 ±1.96/sqrt(n)
+    pub autocorrelation_bound: f64,
+}
+
+// 分组聚合的一行：分组键及各聚合值，没有可聚合数值时为 null
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct GroupRow {
+    pub key: Vec<String>,
+    pub values: Vec<Option<f64>>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct GroupByResult {
+    pub keys: Vec<String>,
+    pub aggregates: Vec<String>,
+    pub groups: Vec<GroupRow>,
+}
+
+// 透视表：columns 为列键组合，每行 values 与之一一对应
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PivotTable {
+    pub row_keys: Vec<String>,
+    pub column_keys: Vec<String>,
+    pub aggregate: String,
+    pub columns: Vec<Vec<String>>,
+    pub rows: Vec<GroupRow>,
 }
 
 // 定义一个错误类型
@@ -41,90 +443,2836 @@ impl AnalysisError {
 #[rocket::main]
 mod data_analyzer {
     use super::*;
+    use cache::{CacheInvalidation, CachedAnalysis, ResultCache};
+    use datasets::{DataSource, DatasetInfo, DatasetStore, DatasetUpload};
+    use rocket::data::{Data, Limits, ToByteUnit};
+    use rocket::form::Form;
+    use rocket::http::ContentType;
     use rocket::State;
     use std::fs::File;
     use std::io::BufReader;
 
-    #[get("/analyze")]
-    async fn analyze(
-        input_file: String,
-        state: &State<HashMap<String, AnalysisResult>>,
-    ) -> Result<Json<AnalysisResult>, status::Custom<&'static str>> {
+    fn bad_request(e: AnalysisError) -> status::Custom<String> {
+        status::Custom(Status::BadRequest, e.to_string())
+    }
+
+    // 读取每行一个数值的文件，第 i 个值对应第 i + 1 行
+    fn read_values(input_file: &Path) -> Result<Vec<f64>, status::Custom<String>> {
         // 尝试打开文件
         let file = File::open(input_file).map_err(|_|
-            status::Custom(Status::InternalServerError, "Failed to open file")
+            status::Custom(Status::InternalServerError, "Failed to open file".to_string())
         )?;
 
         // 创建一个 BufReader 来读取文件
         let reader = BufReader::new(file);
-        let data: Vec<f64> = reader
+        reader
             .lines()
             .map(|line| line.map_err(|_|
-                status::Custom(Status::InternalServerError, "Failed to read line")
+                status::Custom(Status::InternalServerError, "Failed to read line".to_string())
             )?
+            .trim()
             .parse::<f64>()
             .map_err(|_|
-                status::Custom(Status::BadRequest, "Invalid data format")
-            )?)
-            .collect::<Result<_, _>>()?;
+                status::Custom(Status::BadRequest, "Invalid data format".to_string())
+            ))
+            .collect()
+    }
 
-        // 计算总数量
-        let total_count = data.len();
-        // 计算平均值
-        let mean = data.iter().sum::<f64>() / total_count as f64;
-        // 计算中位数
-        let median = data.iter().cloned().copied().collect::<Vec<_>>()[median::median(data.iter().cloned().collect::<Vec<_>>()).unwrap_or(0)] as f64;
-        // 计算众数
-        let mut frequency = HashMap::new();
-        for &value in &data {
-            *frequency.entry(value).or_insert(0) += 1;
-        }
-        let max_frequency = *frequency.values().max().unwrap();
-        let mode = frequency
-            .into_iter()
-            .find(|&(_, count)| count == max_frequency)
-            .map(|(value, _)| value)
-            .unwrap_or(0.0);
+    fn load_table(input_file: &Path, delimiter: Option<&str>, has_header: Option<bool>) -> Result<table::Table, status::Custom<String>> {
+        let delimiter = delimiter.map(table::parse_delimiter).transpose().map_err(bad_request)?;
+        let text = std::fs::read_to_string(input_file).map_err(|_|
+            status::Custom(Status::InternalServerError, "Failed to open file".to_string())
+        )?;
+        table::parse_table(&text, delimiter, has_header).map_err(bad_request)
+    }
 
-        // 将结果存储在共享状态中
-        state.insert(input_file.clone(), AnalysisResult {
-            total_count,
-            mean,
-            median,
-            mode,
-        });
+    // 以下分析接口的数据来源都由 DataSource 从查询参数 dataset 或 input_file 解析
+    #[get("/analyze?<bins>&<percentiles>")]
+    pub async fn analyze(
+        source: Result<DataSource, status::Custom<String>>,
+        bins: Option<usize>,
+        percentiles: Option<&str>,
+        cache: &State<ResultCache>,
+    ) -> Result<Json<AnalysisResult>, status::Custom<String>> {
+        let options = AnalysisOptions::from_query(bins, percentiles)
+            .map_err(|e| status::Custom(Status::BadRequest, e.to_string()))?;
+        let source = source?;
 
-        Ok(Json(AnalysisResult {
-            total_count,
-            mean,
-            median,
-            mode,
-        }))
+        // 内容相同且选项相同的分析直接返回缓存的结果
+        let content_hash = cache::hash_file(&source.path).map_err(|_|
+            status::Custom(Status::InternalServerError, "Failed to open file".to_string())
+        )?;
+        let key = cache::cache_key(&content_hash, &options);
+        if let Some(result) = cache.get(&key) {
+            return Ok(Json(result));
+        }
+
+        let data = read_values(&source.path)?;
+        let result = AnalysisResult::from_values(data, &options)
+            .map_err(|e| status::Custom(Status::BadRequest, e.to_string()))?;
+
+        // 将结果存入缓存，由后台任务定期写入文件
+        let entry = cache::CacheEntry::new(key, content_hash, source.path.display().to_string(), options, result.clone());
+        cache.insert(entry);
+
+        Ok(Json(result))
+    }
+
+    // 列出未过期的缓存分析（不含结果本身）
+    #[get("/cache")]
+    pub fn list_cache(cache: &State<ResultCache>) -> Json<Vec<CachedAnalysis>> {
+        Json(cache.list())
+    }
+
+    #[delete("/cache/<key>")]
+    pub fn invalidate_cache_entry(key: &str, cache: &State<ResultCache>) -> Result<Status, status::Custom<String>> {
+        match cache.remove(|entry| entry.key == key) {
+            Ok(0) => Err(status::Custom(Status::NotFound, format!("No cached analysis with key {}", key))),
+            Ok(_) => Ok(Status::NoContent),
+            Err(e) => Err(status::Custom(Status::InternalServerError, e.to_string())),
+        }
+    }
+
+    // 清空缓存；指定 content_hash 时只删除该内容的所有分析
+    #[delete("/cache?<content_hash>")]
+    pub fn invalidate_cache(
+        content_hash: Option<&str>,
+        cache: &State<ResultCache>,
+    ) -> Result<Json<CacheInvalidation>, status::Custom<String>> {
+        let removed = cache
+            .remove(|entry| content_hash.map_or(true, |hash| entry.content_hash == hash))
+            .map_err(|e| status::Custom(Status::InternalServerError, e.to_string()))?;
+        Ok(Json(CacheInvalidation { removed }))
+    }
+
+    // 分析 CSV/TSV 多列数据：自动识别分隔符和表头，推断每列类型并分别统计
+    #[get("/analyze/table?<delimiter>&<has_header>&<top_k>&<bins>&<percentiles>")]
+    pub async fn analyze_table(
+        source: Result<DataSource, status::Custom<String>>,
+        delimiter: Option<&str>,
+        has_header: Option<bool>,
+        top_k: Option<usize>,
+        bins: Option<usize>,
+        percentiles: Option<&str>,
+    ) -> Result<Json<TableAnalysis>, status::Custom<String>> {
+        let options = AnalysisOptions::from_query(bins, percentiles).map_err(bad_request)?;
+        let table = load_table(&source?.path, delimiter, has_header)?;
+        let analysis = table::analyze_table(&table, &options, top_k.unwrap_or(10)).map_err(bad_request)?;
+        Ok(Json(analysis))
+    }
+
+    // 流式分析超大文件：逐行读取某一列，只保留固定大小的草图
+    #[get("/analyze/stream?<column>&<delimiter>&<has_header>&<percentiles>")]
+    pub async fn analyze_stream(
+        source: Result<DataSource, status::Custom<String>>,
+        column: Option<String>,
+        delimiter: Option<&str>,
+        has_header: Option<bool>,
+        percentiles: Option<&str>,
+    ) -> Result<Json<StreamingAnalysis>, status::Custom<String>> {
+        let options = AnalysisOptions::from_query(None, percentiles).map_err(bad_request)?;
+        let delimiter = delimiter.map(table::parse_delimiter).transpose().map_err(bad_request)?.unwrap_or(b',');
+        let file = File::open(&source?.path).map_err(|_|
+            status::Custom(Status::InternalServerError, "Failed to open file".to_string())
+        )?;
+
+        // 读取和解析是阻塞的，放到专门的线程中执行
+        let analysis = rocket::tokio::task::spawn_blocking(move || {
+            streaming::analyze_reader(
+                BufReader::new(file),
+                delimiter,
+                has_header.unwrap_or(false),
+                column.as_deref(),
+                &options,
+            )
+        })
+        .await
+        .map_err(|_| status::Custom(Status::InternalServerError, "Analysis task failed".to_string()))?
+        .map_err(bad_request)?;
+        Ok(Json(analysis))
+    }
+
+    // 所有数值列两两之间的 Pearson 与 Spearman 相关系数
+    #[get("/analyze/correlation?<delimiter>&<has_header>")]
+    pub async fn correlation(
+        source: Result<DataSource, status::Custom<String>>,
+        delimiter: Option<&str>,
+        has_header: Option<bool>,
+    ) -> Result<Json<CorrelationMatrix>, status::Custom<String>> {
+        let table = load_table(&source?.path, delimiter, has_header)?;
+        let (columns, rows) = table.numeric_rows(None).map_err(bad_request)?;
+        super::correlation::matrix(columns, &rows).map(Json).map_err(bad_request)
+    }
+
+    // 以 target 为因变量拟合线性回归，features 以逗号分隔，默认使用其余所有数值列
+    #[get("/analyze/regression?<target>&<features>&<delimiter>&<has_header>")]
+    pub async fn regression(
+        source: Result<DataSource, status::Custom<String>>,
+        target: String,
+        features: Option<&str>,
+        delimiter: Option<&str>,
+        has_header: Option<bool>,
+    ) -> Result<Json<RegressionResult>, status::Custom<String>> {
+        let table = load_table(&source?.path, delimiter, has_header)?;
+        let features: Vec<String> = match features {
+            Some(features) => features.split(',').map(|f| f.trim().to_string()).collect(),
+            None => table
+                .numeric_columns()
+                .into_iter()
+                .map(|index| table.headers[index].clone())
+                .filter(|name| *name != target)
+                .collect(),
+        };
+        let mut selected = vec![target.clone()];
+        selected.extend(features);
+        let (columns, rows) = table.numeric_rows(Some(&selected)).map_err(bad_request)?;
+        super::regression::ols(columns, &rows).map(Json).map_err(bad_request)
+    }
+
+    // 时间序列分析：按 interval（如 "15m"、"1h"、"1d"，默认取相邻时间差的中位数）重采样，
+    // 计算窗口为 window 的移动平均；给出 period 时做季节分解，lags 为逗号分隔的滞后阶数
+    #[get("/analyze/timeseries?<time_column>&<value_column>&<interval>&<fill>&<window>&<alpha>&<period>&<lags>&<delimiter>&<has_header>")]
+    #[allow(clippy::too_many_arguments)]
+    pub async fn timeseries(
+        source: Result<DataSource, status::Custom<String>>,
+        time_column: Option<&str>,
+        value_column: Option<&str>,
+        interval: Option<&str>,
+        fill: Option<&str>,
+        window: Option<usize>,
+        alpha: Option<f64>,
+        period: Option<usize>,
+        lags: Option<&str>,
+        delimiter: Option<&str>,
+        has_header: Option<bool>,
+    ) -> Result<Json<TimeSeriesAnalysis>, status::Custom<String>> {
+        let fill = match fill {
+            Some(name) => FillStrategy::from_name(name)
+                .ok_or_else(|| bad_request(AnalysisError::new(&format!("Unknown fill strategy: {}", name))))?,
+            None => FillStrategy::Linear,
+        };
+        let options = super::timeseries::TimeSeriesOptions {
+            interval: interval.map(super::timeseries::parse_interval).transpose().map_err(bad_request)?,
+            fill,
+            window: window.unwrap_or(5),
+            alpha,
+            period,
+            lags: lags.map(super::timeseries::parse_lags).transpose().map_err(bad_request)?,
+        };
+        let table = load_table(&source?.path, delimiter, has_header)?;
+        super::timeseries::analyze(&table, time_column, value_column, &options).map(Json).map_err(bad_request)
+    }
+
+    // 以 JSON 或 CSV 返回表格型结果
+    fn tabular<T: Serialize>(
+        format: Option<&str>,
+        result: &T,
+        to_csv: impl FnOnce() -> Result<String, AnalysisError>,
+    ) -> Result<(ContentType, String), status::Custom<String>> {
+        match format.unwrap_or("json") {
+            "json" => rocket::serde::json::serde_json::to_string(result)
+                .map(|body| (ContentType::JSON, body))
+                .map_err(|e| status::Custom(Status::InternalServerError, e.to_string())),
+            "csv" => to_csv().map(|body| (ContentType::CSV, body)).map_err(bad_request),
+            other => Err(bad_request(AnalysisError::new(&format!("Unknown format: {}", other)))),
+        }
+    }
+
+    fn split_names(names: &str) -> Vec<String> {
+        names.split(',').map(|name| name.trim().to_string()).filter(|name| !name.is_empty()).collect()
+    }
+
+    // 按 by 中的列分组，aggregates 形如 "sum(sales),mean(price),count(*),p90(latency)"
+    #[get("/analyze/groupby?<by>&<aggregates>&<format>&<delimiter>&<has_header>")]
+    pub async fn group_by(
+        source: Result<DataSource, status::Custom<String>>,
+        by: &str,
+        aggregates: &str,
+        format: Option<&str>,
+        delimiter: Option<&str>,
+        has_header: Option<bool>,
+    ) -> Result<(ContentType, String), status::Custom<String>> {
+        let aggregates = aggregate::Aggregate::parse_list(aggregates).map_err(bad_request)?;
+        let table = load_table(&source?.path, delimiter, has_header)?;
+        let result = aggregate::group_by(&table, &split_names(by), &aggregates).map_err(bad_request)?;
+        tabular(format, &result, || aggregate::group_by_csv(&result))
+    }
+
+    // 透视表：rows 与 columns 为逗号分隔的键列，aggregate 为单个聚合，默认 count(*)
+    #[get("/analyze/pivot?<rows>&<columns>&<aggregate>&<format>&<delimiter>&<has_header>")]
+    pub async fn pivot(
+        source: Result<DataSource, status::Custom<String>>,
+        rows: &str,
+        columns: &str,
+        aggregate: Option<&str>,
+        format: Option<&str>,
+        delimiter: Option<&str>,
+        has_header: Option<bool>,
+    ) -> Result<(ContentType, String), status::Custom<String>> {
+        let aggregate = aggregate::Aggregate::parse(aggregate.unwrap_or("count(*)")).map_err(bad_request)?;
+        let table = load_table(&source?.path, delimiter, has_header)?;
+        let result = aggregate::pivot(&table, &split_names(rows), &split_names(columns), &aggregate).map_err(bad_request)?;
+        tabular(format, &result, || aggregate::pivot_csv(&result))
+    }
+
+    // 检测离群值，method 为 zscore、mad、iqr 或 isolation_forest，默认 iqr
+    #[get("/analyze/outliers?<method>&<threshold>&<seed>")]
+    pub async fn outliers(
+        source: Result<DataSource, status::Custom<String>>,
+        method: Option<&str>,
+        threshold: Option<f64>,
+        seed: Option<u64>,
+    ) -> Result<Json<OutlierReport>, status::Custom<String>> {
+        let method = match method {
+            Some(name) => OutlierMethod::from_name(name)
+                .ok_or_else(|| bad_request(AnalysisError::new(&format!("Unknown outlier method: {}", name))))?,
+            None => OutlierMethod::Iqr,
+        };
+        let data = read_values(&source?.path)?;
+        super::outliers::detect(&data, method, threshold, seed.unwrap_or(super::outliers::DEFAULT_SEED))
+            .map(Json)
+            .map_err(bad_request)
+    }
+
+    fn upload_failed(e: io::Error) -> status::Custom<String> {
+        status::Custom(Status::InternalServerError, format!("Failed to store dataset: {}", e))
+    }
+
+    // 以 multipart 表单上传数据集，文件放在 file 字段中；
+    // 启动时 "file" 和 "data-form" 限制已提高到 "dataset" 限制，与直接上传的上限一致
+    #[post("/datasets", format = "multipart/form-data", data = "<upload>")]
+    pub async fn upload_dataset_form(
+        mut upload: Form<DatasetUpload<'_>>,
+        store: &State<DatasetStore>,
+    ) -> Result<status::Created<Json<DatasetInfo>>, status::Custom<String>> {
+        let name = upload
+            .name
+            .clone()
+            .or_else(|| upload.file.name().map(str::to_string))
+            .unwrap_or_default();
+        let (id, path) = store.allocate();
+        // 跨文件系统时是复制，失败可能留下写了一半的文件
+        if let Err(e) = upload.file.move_copy_to(&path).await {
+            let _ = std::fs::remove_file(&path);
+            return Err(upload_failed(e));
+        }
+        let info = store.register(id, name).map_err(|e| {
+            let _ = std::fs::remove_file(&path);
+            upload_failed(e)
+        })?;
+        Ok(status::Created::new(format!("/datasets/{}", info.id)).body(Json(info)))
+    }
+
+    // 直接以请求体上传数据集，大小受 "dataset" 限制约束（默认 64 MiB）
+    #[post("/datasets?<name>", data = "<body>", rank = 2)]
+    pub async fn upload_dataset(
+        name: Option<String>,
+        body: Data<'_>,
+        limits: &Limits,
+        store: &State<DatasetStore>,
+    ) -> Result<status::Created<Json<DatasetInfo>>, status::Custom<String>> {
+        let limit = limits.get("dataset").unwrap_or_else(|| 64.mebibytes());
+        let (id, path) = store.allocate();
+        // 写入失败、超出限制或登记失败时都不能留下 .data 文件
+        let written = match body.open(limit).into_file(&path).await {
+            Ok(written) => written,
+            Err(e) => {
+                let _ = std::fs::remove_file(&path);
+                return Err(upload_failed(e));
+            }
+        };
+        if !written.is_complete() {
+            let _ = std::fs::remove_file(&path);
+            return Err(status::Custom(Status::PayloadTooLarge, format!("Dataset exceeds the {} upload limit", limit)));
+        }
+        let info = store.register(id, name.unwrap_or_default()).map_err(|e| {
+            let _ = std::fs::remove_file(&path);
+            upload_failed(e)
+        })?;
+        Ok(status::Created::new(format!("/datasets/{}", info.id)).body(Json(info)))
+    }
+
+    #[get("/datasets")]
+    pub fn list_datasets(store: &State<DatasetStore>) -> Json<Vec<DatasetInfo>> {
+        Json(store.list())
+    }
+
+    #[delete("/datasets/<id>")]
+    pub fn delete_dataset(id: &str, store: &State<DatasetStore>) -> Result<Status, status::Custom<String>> {
+        match store.remove(id) {
+            Ok(true) => Ok(Status::NoContent),
+            Ok(false) => Err(status::Custom(Status::NotFound, format!("Unknown dataset: {}", id))),
+            Err(e) => Err(status::Custom(Status::InternalServerError, e.to_string())),
+        }
     }
 }
 
-// 定义一个模块来计算中位数
-mod median {
-    // 计算中位数的函数
-    pub fn median<T: Clone + std::cmp::PartialOrd + Copy>(values: Vec<T>) -> Option<T> {
-        let size = values.len();
-        if size == 0 {
-            return None;
+// 分析结果缓存：以内容哈希和分析选项为键，带过期时间与 LRU 淘汰，并持久化到本地文件
+mod cache {
+    use super::*;
+    use rocket::serde::json::serde_json;
+    use sha2::{Digest, Sha256};
+    use std::io::Read;
+    use std::sync::{Arc, Mutex};
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    #[derive(Debug, Deserialize)]
+    #[serde(default)]
+    pub struct CacheConfig {
+        pub path: PathBuf,
+        pub ttl_secs: u64,
+        pub capacity: usize,
+        // 改动积攒后写入文件的间隔
+        pub flush_secs: u64,
+    }
+
+    impl Default for CacheConfig {
+        fn default() -> Self {
+            CacheConfig {
+                path: std::env::temp_dir().join("analyzer-cache.json"),
+                ttl_secs: 3600,
+                capacity: 256,
+                flush_secs: 5,
+            }
+        }
+    }
+
+    fn now_secs() -> u64 {
+        SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or_default()
+    }
+
+    // 文件内容的 SHA-256，按块读取以免把大文件整个载入内存
+    pub fn hash_file(path: &Path) -> io::Result<String> {
+        let mut file = std::fs::File::open(path)?;
+        let mut hasher = Sha256::new();
+        let mut buffer = [0u8; 64 * 1024];
+        loop {
+            let n = file.read(&mut buffer)?;
+            if n == 0 {
+                break;
+            }
+            hasher.update(&buffer[..n]);
+        }
+        Ok(format!("{:x}", hasher.finalize()))
+    }
+
+    pub fn cache_key(content_hash: &str, options: &AnalysisOptions) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(content_hash.as_bytes());
+        hasher.update(serde_json::to_vec(options).expect("options are serializable"));
+        format!("{:x}", hasher.finalize())
+    }
+
+    #[derive(Debug, Serialize, Deserialize, Clone)]
+    pub struct CacheEntry {
+        pub key: String,
+        pub content_hash: String,
+        pub source: String,
+        pub options: AnalysisOptions,
+        pub result: AnalysisResult,
+        pub created_at: u64,
+        // 最近一次访问的序号，越小越久未使用
+        pub last_used: u64,
+    }
+
+    impl CacheEntry {
+        pub fn new(key: String, content_hash: String, source: String, options: AnalysisOptions, result: AnalysisResult) -> CacheEntry {
+            CacheEntry { key, content_hash, source, options, result, created_at: now_secs(), last_used: 0 }
+        }
+    }
+
+    // 缓存列表中的一项，不包含分析结果
+    #[derive(Debug, Serialize, Deserialize, Clone)]
+    pub struct CachedAnalysis {
+        pub key: String,
+        pub content_hash: String,
+        pub source: String,
+        pub options: AnalysisOptions,
+        pub created_at: u64,
+        pub expires_at: u64,
+    }
+
+    #[derive(Debug, Serialize, Deserialize, Clone)]
+    pub struct CacheInvalidation {
+        pub removed: usize,
+    }
+
+    struct Entries {
+        map: HashMap<String, CacheEntry>,
+        clock: u64,
+        // 自上次写入文件以来是否有改动
+        dirty: bool,
+    }
+
+    // 克隆的句柄共享同一份缓存，供后台写入任务使用
+    #[derive(Clone)]
+    pub struct ResultCache {
+        path: PathBuf,
+        ttl_secs: u64,
+        capacity: usize,
+        pub flush_interval: std::time::Duration,
+        entries: Arc<Mutex<Entries>>,
+        // 保证同一时间只有一次写文件
+        writer: Arc<Mutex<()>>,
+    }
+
+    impl ResultCache {
+        // 从文件恢复缓存，文件不存在或已损坏时从空缓存开始
+        pub fn load(config: CacheConfig) -> io::Result<ResultCache> {
+            let mut map = HashMap::new();
+            match std::fs::read(&config.path) {
+                Ok(bytes) => match serde_json::from_slice::<Vec<CacheEntry>>(&bytes) {
+                    Ok(entries) => map.extend(entries.into_iter().map(|entry| (entry.key.clone(), entry))),
+                    Err(e) => eprintln!("Ignoring the unreadable analysis cache {}: {}", config.path.display(), e),
+                },
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+                Err(e) => return Err(e),
+            }
+            let deadline = now_secs().saturating_sub(config.ttl_secs);
+            map.retain(|_, entry| entry.created_at > deadline);
+            let clock = map.values().map(|entry| entry.last_used).max().unwrap_or(0);
+            Ok(ResultCache {
+                path: config.path,
+                ttl_secs: config.ttl_secs,
+                capacity: config.capacity.max(1),
+                flush_interval: std::time::Duration::from_secs(config.flush_secs.max(1)),
+                entries: Arc::new(Mutex::new(Entries { map, clock, dirty: false })),
+                writer: Arc::new(Mutex::new(())),
+            })
+        }
+
+        fn expire(&self, entries: &mut Entries) -> usize {
+            let deadline = now_secs().saturating_sub(self.ttl_secs);
+            let before = entries.map.len();
+            entries.map.retain(|_, entry| entry.created_at > deadline);
+            let expired = before - entries.map.len();
+            entries.dirty |= expired > 0;
+            expired
+        }
+
+        // 有改动时把缓存写入文件：锁内只复制条目，序列化和写文件在锁外进行；
+        // 先写临时文件再重命名，避免进程中断时留下半个文件
+        pub fn flush(&self) -> io::Result<()> {
+            let _writer = self.writer.lock().unwrap();
+            let list: Vec<CacheEntry> = {
+                let mut entries = self.entries.lock().unwrap();
+                if !entries.dirty {
+                    return Ok(());
+                }
+                entries.dirty = false;
+                entries.map.values().cloned().collect()
+            };
+            let written = serde_json::to_vec(&list)
+                .map_err(|e| io::Error::new(io::ErrorKind::Other, e))
+                .and_then(|json| {
+                    let tmp = self.path.with_extension("json.tmp");
+                    std::fs::write(&tmp, json)?;
+                    std::fs::rename(&tmp, &self.path)
+                });
+            if written.is_err() {
+                self.entries.lock().unwrap().dirty = true;
+            }
+            written
+        }
+
+        pub fn get(&self, key: &str) -> Option<AnalysisResult> {
+            let mut entries = self.entries.lock().unwrap();
+            self.expire(&mut entries);
+            entries.clock += 1;
+            let clock = entries.clock;
+            let entry = entries.map.get_mut(key)?;
+            entry.last_used = clock;
+            Some(entry.result.clone())
+        }
+
+        // 超出容量时淘汰最久未使用的条目；改动由 flush 定期写入文件
+        pub fn insert(&self, mut entry: CacheEntry) {
+            let mut entries = self.entries.lock().unwrap();
+            self.expire(&mut entries);
+            entries.clock += 1;
+            entry.last_used = entries.clock;
+            entries.map.insert(entry.key.clone(), entry);
+            while entries.map.len() > self.capacity {
+                let oldest = entries
+                    .map
+                    .values()
+                    .min_by_key(|entry| entry.last_used)
+                    .map(|entry| entry.key.clone())
+                    .expect("cache is not empty");
+                entries.map.remove(&oldest);
+            }
+            entries.dirty = true;
         }
-        if size % 2 == 1 {
-            Some(*values
+
+        pub fn list(&self) -> Vec<CachedAnalysis> {
+            let mut entries = self.entries.lock().unwrap();
+            self.expire(&mut entries);
+            let mut list: Vec<CachedAnalysis> = entries
+                .map
+                .values()
+                .map(|entry| CachedAnalysis {
+                    key: entry.key.clone(),
+                    content_hash: entry.content_hash.clone(),
+                    source: entry.source.clone(),
+                    options: entry.options.clone(),
+                    created_at: entry.created_at,
+                    expires_at: entry.created_at + self.ttl_secs,
+                })
+                .collect();
+            list.sort_by(|a, b| b.created_at.cmp(&a.created_at));
+            list
+        }
+
+        // 删除满足条件的条目，返回删除的数量；删除会立即写入文件
+        pub fn remove(&self, matches: impl Fn(&CacheEntry) -> bool) -> io::Result<usize> {
+            let removed = {
+                let mut entries = self.entries.lock().unwrap();
+                let before = entries.map.len();
+                entries.map.retain(|_, entry| !matches(entry));
+                let removed = before - entries.map.len();
+                entries.dirty |= removed > 0;
+                removed
+            };
+            self.flush()?;
+            Ok(removed)
+        }
+    }
+}
+
+// 数据来源：服务器上受限目录中的文件，或者通过上传登记的数据集
+mod datasets {
+    use super::*;
+    use rocket::fs::TempFile;
+    use rocket::request::{FromRequest, Outcome, Request};
+    use rocket::serde::json::serde_json;
+    use std::sync::Mutex;
+
+    // 允许访问的数据根目录，保存的是规范化后的绝对路径
+    pub struct DataSandbox {
+        roots: Vec<PathBuf>,
+    }
+
+    impl DataSandbox {
+        pub fn new(roots: &[PathBuf]) -> io::Result<DataSandbox> {
+            let roots = roots
                 .iter()
-                .nth(size / 2)
-                .expect("Index out of bounds"))
-        } else {
-            let mid = size / 2;
-            let (left, right) = values.split_at(mid);
-            Some(
-                left.last()
-                    .zip(right.first())
-                    .map(|(left, right)| (left + right) / 2)
-                    .expect("Index out of bounds"),
-            )
+                .map(|root| {
+                    std::fs::create_dir_all(root)?;
+                    std::fs::canonicalize(root)
+                })
+                .collect::<io::Result<_>>()?;
+            Ok(DataSandbox { roots })
+        }
+
+        // 相对路径依次在各个根目录下查找；规范化会解析 ".." 和符号链接，
+        // 因此只需检查结果是否仍位于某个根目录之内
+        pub fn resolve(&self, input_file: &str) -> Result<PathBuf, status::Custom<String>> {
+            let mut outside = false;
+            for root in &self.roots {
+                let Ok(path) = std::fs::canonicalize(root.join(input_file)) else {
+                    continue;
+                };
+                if !self.roots.iter().any(|root| path.starts_with(root)) {
+                    outside = true;
+                } else if path.is_file() {
+                    return Ok(path);
+                }
+            }
+            if outside {
+                Err(status::Custom(Status::Forbidden, "Path is outside the configured data roots".to_string()))
+            } else {
+                Err(status::Custom(Status::NotFound, format!("File not found: {}", input_file)))
+            }
+        }
+    }
+
+    #[derive(Debug, Serialize, Deserialize, Clone)]
+    pub struct DatasetInfo {
+        pub id: String,
+        pub name: String,
+        pub size: u64,
+        pub uploaded_at: String,
+    }
+
+    #[derive(rocket::FromForm)]
+    pub struct DatasetUpload<'r> {
+        pub file: TempFile<'r>,
+        pub name: Option<String>,
+    }
+
+    // 上传的数据集，内容保存为 <id>.data，元数据保存为 <id>.json
+    pub struct DatasetStore {
+        dir: PathBuf,
+        datasets: Mutex<HashMap<String, DatasetInfo>>,
+    }
+
+    impl DatasetStore {
+        pub fn load(dir: PathBuf) -> io::Result<DatasetStore> {
+            std::fs::create_dir_all(&dir)?;
+            let mut datasets = HashMap::new();
+            for entry in std::fs::read_dir(&dir)? {
+                let path = entry?.path();
+                if path.extension().map_or(true, |ext| ext != "json") {
+                    continue;
+                }
+                // 元数据损坏或内容缺失的数据集直接忽略
+                let info = std::fs::read(&path)
+                    .ok()
+                    .and_then(|bytes| serde_json::from_slice::<DatasetInfo>(&bytes).ok());
+                if let Some(info) = info.filter(|info| dir.join(format!("{}.data", info.id)).is_file()) {
+                    datasets.insert(info.id.clone(), info);
+                }
+            }
+            Ok(DatasetStore { dir, datasets: Mutex::new(datasets) })
+        }
+
+        // 分配新的 id 以及保存内容的路径
+        pub fn allocate(&self) -> (String, PathBuf) {
+            let id = uuid::Uuid::new_v4().simple().to_string();
+            let path = self.dir.join(format!("{}.data", id));
+            (id, path)
+        }
+
+        // 内容写入完成后登记数据集并持久化元数据
+        pub fn register(&self, id: String, name: String) -> io::Result<DatasetInfo> {
+            let size = std::fs::metadata(self.dir.join(format!("{}.data", id)))?.len();
+            let info = DatasetInfo { id, name, size, uploaded_at: chrono::Utc::now().to_rfc3339() };
+            let json = serde_json::to_vec(&info).map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+            std::fs::write(self.dir.join(format!("{}.json", info.id)), json)?;
+            self.datasets.lock().unwrap().insert(info.id.clone(), info.clone());
+            Ok(info)
+        }
+
+        pub fn path(&self, id: &str) -> Option<PathBuf> {
+            let datasets = self.datasets.lock().unwrap();
+            datasets.contains_key(id).then(|| self.dir.join(format!("{}.data", id)))
+        }
+
+        pub fn list(&self) -> Vec<DatasetInfo> {
+            let mut datasets: Vec<DatasetInfo> = self.datasets.lock().unwrap().values().cloned().collect();
+            datasets.sort_by(|a, b| a.uploaded_at.cmp(&b.uploaded_at));
+            datasets
         }
+
+        // 先删文件再移除登记，删除失败时数据集仍然可见，可以重试；
+        // 先删元数据，这样即使内容没删掉，重启后也不会再加载它
+        pub fn remove(&self, id: &str) -> io::Result<bool> {
+            let mut datasets = self.datasets.lock().unwrap();
+            if !datasets.contains_key(id) {
+                return Ok(false);
+            }
+            // 重试时前一次可能已经删掉了其中一个文件
+            let delete = |path: PathBuf| match std::fs::remove_file(path) {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+                _ => Ok(()),
+            };
+            delete(self.dir.join(format!("{}.json", id)))?;
+            delete(self.dir.join(format!("{}.data", id)))?;
+            datasets.remove(id);
+            Ok(true)
+        }
+    }
+
+    // 分析接口的数据来源：查询参数 dataset 为已上传数据集的 id，
+    // input_file 为某个数据根目录下的相对路径
+    pub struct DataSource {
+        pub path: PathBuf,
+    }
+
+    #[rocket::async_trait]
+    impl<'r> FromRequest<'r> for DataSource {
+        type Error = status::Custom<String>;
+
+        async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+            let fail = |status: status::Custom<String>| Outcome::Error((status.0, status));
+            let dataset = request.query_value::<&str>("dataset").and_then(Result::ok);
+            let input_file = request.query_value::<&str>("input_file").and_then(Result::ok);
+            match (dataset, input_file) {
+                (Some(id), None) => {
+                    let store = request.rocket().state::<DatasetStore>().expect("dataset store is managed");
+                    match store.path(id) {
+                        Some(path) => Outcome::Success(DataSource { path }),
+                        None => fail(status::Custom(Status::NotFound, format!("Unknown dataset: {}", id))),
+                    }
+                }
+                (None, Some(input_file)) => {
+                    let sandbox = request.rocket().state::<DataSandbox>().expect("data sandbox is managed");
+                    match sandbox.resolve(input_file) {
+                        Ok(path) => Outcome::Success(DataSource { path }),
+                        Err(e) => fail(e),
+                    }
+                }
+                _ => fail(status::Custom(
+                    Status::BadRequest,
+                    "Exactly one of dataset or input_file is required".to_string(),
+                )),
+            }
+        }
+    }
+}
+
+#[path = "response_compression_fairing.rs"]
+mod response_compression;
+
+use rocket::data::{Limits, ToByteUnit};
+use rocket::fairing::AdHoc;
+
+// 启动服务，分析结果可能很大，因此对响应启用压缩
+#[launch]
+fn rocket() -> _ {
+    // 表单上传的文件受 "file" 和 "data-form" 限制（默认 1 MiB 和 2 MiB），提高到 "dataset" 限制
+    let figment = rocket::Config::figment();
+    let limits: Limits = figment.extract_inner("limits").unwrap_or_default();
+    let dataset_limit = limits.get("dataset").unwrap_or_else(|| 64.mebibytes());
+    let limits = limits.limit("file", dataset_limit).limit("data-form", dataset_limit + 1.mebibytes());
+    let rocket = rocket::custom(figment.merge(("limits", limits)));
+    // 通过 input_file 只能访问这些目录中的文件
+    let data_roots: Vec<PathBuf> = rocket
+        .figment()
+        .extract_inner("data_roots")
+        .unwrap_or_else(|_| vec![PathBuf::from("data")]);
+    let dataset_dir: PathBuf = rocket
+        .figment()
+        .extract_inner("dataset_dir")
+        .unwrap_or_else(|_| std::env::temp_dir().join("analyzer-datasets"));
+    let cache_config: cache::CacheConfig = rocket.figment().extract_inner("cache").unwrap_or_default();
+    let sandbox = datasets::DataSandbox::new(&data_roots).expect("Failed to open the data roots");
+    let store = datasets::DatasetStore::load(dataset_dir).expect("Failed to load uploaded datasets");
+    rocket
+        .attach(response_compression::ResponseCompression::default())
+        .manage(cache::ResultCache::load(cache_config).expect("Failed to load the analysis cache"))
+        .manage(sandbox)
+        .manage(store)
+        .attach(AdHoc::on_liftoff("Cache writer", |rocket| {
+            Box::pin(async move {
+                // 定期把缓存的改动写入文件，而不是每次插入都重写整个文件
+                let cache = rocket.state::<cache::ResultCache>().expect("cache is managed").clone();
+                rocket::tokio::spawn(async move {
+                    let mut interval = rocket::tokio::time::interval(cache.flush_interval);
+                    loop {
+                        interval.tick().await;
+                        let cache = cache.clone();
+                        if let Ok(Err(e)) = rocket::tokio::task::spawn_blocking(move || cache.flush()).await {
+                            eprintln!("Failed to persist the analysis cache: {}", e);
+                        }
+                    }
+                });
+            })
+        }))
+        .attach(AdHoc::on_shutdown("Cache flush", |rocket| {
+            Box::pin(async move {
+                if let Some(Err(e)) = rocket.state::<cache::ResultCache>().map(cache::ResultCache::flush) {
+                    eprintln!("Failed to persist the analysis cache: {}", e);
+                }
+            })
+        }))
+        .mount("/", routes![
+            data_analyzer::analyze,
+            data_analyzer::analyze_table,
+            data_analyzer::analyze_stream,
+            data_analyzer::correlation,
+            data_analyzer::regression,
+            data_analyzer::outliers,
+            data_analyzer::timeseries,
+            data_analyzer::group_by,
+            data_analyzer::pivot,
+            data_analyzer::upload_dataset_form,
+            data_analyzer::upload_dataset,
+            data_analyzer::list_datasets,
+            data_analyzer::delete_dataset,
+            data_analyzer::list_cache,
+            data_analyzer::invalidate_cache_entry,
+            data_analyzer::invalidate_cache,
+        ])
+}
+
+// 描述统计的计算函数，除 mean/variance 外都要求输入已按升序排序且非空
+mod statistics {
+    use super::HistogramBin;
+
+    pub fn mean(data: &[f64]) -> f64 {
+        data.iter().sum::<f64>() / data.len() as f64
+    }
+
+    // 样本方差，只有一个值时为 0
+    pub fn variance(data: &[f64], mean: f64) -> f64 {
+        if data.len() < 2 {
+            return 0.0;
+        }
+        data.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / (data.len() - 1) as f64
+    }
+
+    // k 阶中心矩（除以 n）
+    fn central_moment(data: &[f64], mean: f64, k: i32) -> f64 {
+        data.iter().map(|v| (v - mean).powi(k)).sum::<f64>() / data.len() as f64
+    }
+
+    // 偏度 g1 = m3 / m2^1.5，所有值相同时为 0
+    pub fn skewness(data: &[f64], mean: f64) -> f64 {
+        let m2 = central_moment(data, mean, 2);
+        if m2 == 0.0 {
+            return 0.0;
+        }
+        central_moment(data, mean, 3) / m2.powf(1.5)
+    }
+
+    // 超额峰度 g2 = m4 / m2^2 - 3，所有值相同时为 0
+    pub fn kurtosis(data: &[f64], mean: f64) -> f64 {
+        let m2 = central_moment(data, mean, 2);
+        if m2 == 0.0 {
+            return 0.0;
+        }
+        central_moment(data, mean, 4) / (m2 * m2) - 3.0
+    }
+
+    // 百分位数（p 取 0..=100），在相邻两个值之间线性插值
+    pub fn percentile(sorted: &[f64], p: f64) -> f64 {
+        let rank = p / 100.0 * (sorted.len() - 1) as f64;
+        let lower = rank.floor() as usize;
+        let upper = rank.ceil() as usize;
+        sorted[lower] + (sorted[upper] - sorted[lower]) * (rank - lower as f64)
+    }
+
+    // 中位数：奇数个取中间值，偶数个取中间两个的平均值
+    pub fn median(sorted: &[f64]) -> f64 {
+        percentile(sorted, 50.0)
+    }
+
+    // 众数：出现次数最多的值，次数相同时取较小的值
+    pub fn mode(sorted: &[f64]) -> f64 {
+        let mut best = (sorted[0], 0);
+        let mut start = 0;
+        while start < sorted.len() {
+            let end = start + sorted[start..].iter().take_while(|&&v| v == sorted[start]).count();
+            if end - start > best.1 {
+                best = (sorted[start], end - start);
+            }
+            start = end;
+        }
+        best.0
+    }
+
+    // 等宽直方图；所有值相同时只有一个区间
+    pub fn histogram(sorted: &[f64], bins: usize) -> Vec<HistogramBin> {
+        let min = sorted[0];
+        let max = sorted[sorted.len() - 1];
+        if min == max {
+            return vec![HistogramBin { lower: min, upper: max, count: sorted.len() }];
+        }
+        let width = (max - min) / bins as f64;
+        let mut histogram: Vec<HistogramBin> = (0..bins)
+            .map(|i| HistogramBin { lower: min + width * i as f64, upper: min + width * (i + 1) as f64, count: 0 })
+            .collect();
+        histogram[bins - 1].upper = max;
+        for &value in sorted {
+            let index = (((value - min) / width) as usize).min(bins - 1);
+            histogram[index].count += 1;
+        }
+        histogram
+    }
+}
+
+// 多列 CSV/TSV 数据的解析与按列统计
+mod table {
+    use super::*;
+    use chrono::NaiveDateTime;
+
+    // 表格数据：列名与按行存放的原始单元格
+    pub struct Table {
+        pub headers: Vec<String>,
+        pub has_header: bool,
+        pub rows: Vec<Vec<String>>,
+    }
+
+    impl Table {
+        // 某一列的所有单元格，缺失的单元格视为空
+        pub fn column(&self, index: usize) -> impl Iterator<Item = &str> {
+            self.rows.iter().map(move |row| row.get(index).map(String::as_str).unwrap_or(""))
+        }
+
+        pub fn column_index(&self, name: &str) -> Result<usize, AnalysisError> {
+            self.headers
+                .iter()
+                .position(|h| h == name)
+                .ok_or_else(|| AnalysisError::new(&format!("Unknown column: {}", name)))
+        }
+
+        // 第一个推断为指定类型的列
+        pub fn first_column_of(&self, column_type: ColumnType) -> Option<usize> {
+            (0..self.headers.len()).find(|&index| infer_type(self.column(index)) == column_type)
+        }
+
+        // 所有推断为数值类型的列的下标
+        pub fn numeric_columns(&self) -> Vec<usize> {
+            (0..self.headers.len())
+                .filter(|&index| infer_type(self.column(index)) == ColumnType::Numeric)
+                .collect()
+        }
+
+        // 取出指定数值列（默认全部数值列）中所有列都不为空的行
+        pub fn numeric_rows(&self, names: Option<&[String]>) -> Result<(Vec<String>, Vec<Vec<f64>>), AnalysisError> {
+            let indices = match names {
+                Some(names) => names
+                    .iter()
+                    .map(|name| {
+                        let index = self.column_index(name)?;
+                        if infer_type(self.column(index)) != ColumnType::Numeric {
+                            return Err(AnalysisError::new(&format!("Column {} is not numeric", name)));
+                        }
+                        Ok(index)
+                    })
+                    .collect::<Result<Vec<_>, _>>()?,
+                None => self.numeric_columns(),
+            };
+            let rows = self
+                .rows
+                .iter()
+                .filter_map(|row| {
+                    indices
+                        .iter()
+                        .map(|&index| row.get(index).and_then(|cell| parse_number(cell)))
+                        .collect::<Option<Vec<f64>>>()
+                })
+                .collect();
+            let columns = indices.iter().map(|&index| self.headers[index].clone()).collect();
+            Ok((columns, rows))
+        }
+    }
+
+    // 查询参数中的分隔符，可以是名称或单个字符
+    pub fn parse_delimiter(name: &str) -> Result<u8, AnalysisError> {
+        match name {
+            "comma" => Ok(b','),
+            "tab" => Ok(b'\t'),
+            "semicolon" => Ok(b';'),
+            "pipe" => Ok(b'|'),
+            _ if name.len() == 1 && name.is_ascii() => Ok(name.as_bytes()[0]),
+            _ => Err(AnalysisError::new("delimiter must be comma, tab, semicolon, pipe or a single character")),
+        }
+    }
+
+    // 根据第一行中制表符和逗号的数量猜测分隔符
+    fn detect_delimiter(text: &str) -> u8 {
+        let first_line = text.lines().next().unwrap_or_default();
+        let tabs = first_line.matches('\t').count();
+        let commas = first_line.matches(',').count();
+        let semicolons = first_line.matches(';').count();
+        if tabs >= commas && tabs >= semicolons && tabs > 0 {
+            b'\t'
+        } else if semicolons > commas {
+            b';'
+        } else {
+            b','
+        }
+    }
+
+    pub fn is_null(cell: &str) -> bool {
+        matches!(cell.trim().to_ascii_lowercase().as_str(), "" | "na" | "n/a" | "null" | "none" | "nan" | "-")
+    }
+
+    pub fn parse_number(cell: &str) -> Option<f64> {
+        cell.trim().parse::<f64>().ok().filter(|v| v.is_finite())
+    }
+
+    pub fn parse_bool(cell: &str) -> Option<bool> {
+        match cell.trim().to_ascii_lowercase().as_str() {
+            "true" | "yes" | "y" => Some(true),
+            "false" | "no" | "n" => Some(false),
+            _ => None,
+        }
+    }
+
+    // 支持 RFC 3339、"YYYY-MM-DD HH:MM:SS"、"YYYY-MM-DD" 和 "YYYY/MM/DD"
+    pub fn parse_datetime(cell: &str) -> Option<NaiveDateTime> {
+        let cell = cell.trim();
+        if let Ok(time) = chrono::DateTime::parse_from_rfc3339(cell) {
+            return Some(time.naive_utc());
+        }
+        for format in ["%Y-%m-%d %H:%M:%S", "%Y-%m-%dT%H:%M:%S", "%Y/%m/%d %H:%M:%S"] {
+            if let Ok(time) = NaiveDateTime::parse_from_str(cell, format) {
+                return Some(time);
+            }
+        }
+        for format in ["%Y-%m-%d", "%Y/%m/%d"] {
+            if let Ok(date) = chrono::NaiveDate::parse_from_str(cell, format) {
+                return date.and_hms_opt(0, 0, 0);
+            }
+        }
+        None
+    }
+
+    // 非空单元格全部能解析为同一种类型时取该类型，否则视为分类列
+    pub fn infer_type<'a>(cells: impl Iterator<Item = &'a str>) -> ColumnType {
+        let (mut numeric, mut boolean, mut datetime, mut any) = (true, true, true, false);
+        for cell in cells.filter(|cell| !is_null(cell)) {
+            any = true;
+            numeric &= parse_number(cell).is_some();
+            boolean &= parse_bool(cell).is_some();
+            datetime &= parse_datetime(cell).is_some();
+            if !(numeric || boolean || datetime) {
+                break;
+            }
+        }
+        match (any, numeric, boolean, datetime) {
+            (false, ..) => ColumnType::Categorical,
+            (true, true, _, _) => ColumnType::Numeric,
+            (true, _, true, _) => ColumnType::Boolean,
+            (true, _, _, true) => ColumnType::Datetime,
+            _ => ColumnType::Categorical,
+        }
+    }
+
+    // 第一行没有任何数值、布尔或时间单元格，而其余行中有，就认为第一行是表头
+    fn looks_like_header(rows: &[Vec<String>]) -> bool {
+        let typed = |cell: &str| parse_number(cell).is_some() || parse_bool(cell).is_some() || parse_datetime(cell).is_some();
+        let Some(first) = rows.first() else {
+            return false;
+        };
+        if first.iter().any(|cell| is_null(cell) || typed(cell)) {
+            return false;
+        }
+        rows.len() == 1 || rows[1..].iter().any(|row| row.iter().any(|cell| typed(cell)))
+    }
+
+    pub fn parse_table(text: &str, delimiter: Option<u8>, has_header: Option<bool>) -> Result<Table, AnalysisError> {
+        let delimiter = delimiter.unwrap_or_else(|| detect_delimiter(text));
+        let mut reader = csv::ReaderBuilder::new()
+            .delimiter(delimiter)
+            .has_headers(false)
+            .flexible(true)
+            .from_reader(text.as_bytes());
+        let mut rows: Vec<Vec<String>> = reader
+            .records()
+            .map(|record| record.map(|r| r.iter().map(str::to_string).collect()))
+            .collect::<Result<_, _>>()
+            .map_err(|e| AnalysisError::new(&format!("Invalid CSV data: {}", e)))?;
+        rows.retain(|row| !(row.len() == 1 && row[0].trim().is_empty()));
+        if rows.is_empty() {
+            return Err(AnalysisError::new("No data to analyze"));
+        }
+
+        let has_header = has_header.unwrap_or_else(|| looks_like_header(&rows));
+        let width = rows.iter().map(Vec::len).max().unwrap_or(0);
+        let headers = if has_header {
+            let mut headers: Vec<String> = rows.remove(0).into_iter().map(|h| h.trim().to_string()).collect();
+            headers.extend((headers.len()..width).map(|i| format!("column_{}", i + 1)));
+            headers
+        } else {
+            (0..width).map(|i| format!("column_{}", i + 1)).collect()
+        };
+        Ok(Table { headers, has_header, rows })
+    }
+
+    fn analyze_column(table: &Table, index: usize, options: &AnalysisOptions, top_k: usize) -> Result<ColumnAnalysis, AnalysisError> {
+        let values: Vec<&str> = table.column(index).filter(|cell| !is_null(cell)).map(str::trim).collect();
+        let column_type = infer_type(values.iter().copied());
+        let mut column = ColumnAnalysis {
+            name: table.headers[index].clone(),
+            column_type,
+            count: values.len(),
+            null_count: table.rows.len() - values.len(),
+            numeric: None,
+            categorical: None,
+            boolean: None,
+            datetime: None,
+        };
+        match column_type {
+            ColumnType::Numeric => {
+                let numbers = values.iter().filter_map(|cell| parse_number(cell)).collect();
+                column.numeric = Some(AnalysisResult::from_values(numbers, options)?);
+            }
+            ColumnType::Boolean => {
+                let true_count = values.iter().filter(|cell| parse_bool(cell) == Some(true)).count();
+                column.boolean = Some(BooleanSummary { true_count, false_count: values.len() - true_count });
+            }
+            ColumnType::Datetime => {
+                let times: Vec<NaiveDateTime> = values.iter().filter_map(|cell| parse_datetime(cell)).collect();
+                let format = |time: Option<&NaiveDateTime>| time.map(|t| t.format("%Y-%m-%dT%H:%M:%S").to_string());
+                column.datetime = Some(DatetimeSummary {
+                    min: format(times.iter().min()).unwrap_or_default(),
+                    max: format(times.iter().max()).unwrap_or_default(),
+                });
+            }
+            ColumnType::Categorical => {
+                let mut counts: HashMap<&str, usize> = HashMap::new();
+                for value in &values {
+                    *counts.entry(value).or_insert(0) += 1;
+                }
+                let cardinality = counts.len();
+                let mut top_values: Vec<ValueCount> =
+                    counts.into_iter().map(|(value, count)| ValueCount { value: value.to_string(), count }).collect();
+                top_values.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.value.cmp(&b.value)));
+                top_values.truncate(top_k);
+                column.categorical = Some(CategoricalSummary { cardinality, top_values });
+            }
+        }
+        Ok(column)
+    }
+
+    pub fn analyze_table(table: &Table, options: &AnalysisOptions, top_k: usize) -> Result<TableAnalysis, AnalysisError> {
+        let columns = (0..table.headers.len())
+            .map(|index| analyze_column(table, index, options, top_k))
+            .collect::<Result<_, _>>()?;
+        Ok(TableAnalysis { row_count: table.rows.len(), has_header: table.has_header, columns })
+    }
+}
+
+// 相关系数矩阵
+mod correlation {
+    use super::*;
+
+    // Pearson 相关系数，任一列方差为 0 时无定义
+    pub fn pearson(x: &[f64], y: &[f64]) -> Option<f64> {
+        let mean_x = statistics::mean(x);
+        let mean_y = statistics::mean(y);
+        let (mut sxy, mut sxx, mut syy) = (0.0, 0.0, 0.0);
+        for (a, b) in x.iter().zip(y) {
+            sxy += (a - mean_x) * (b - mean_y);
+            sxx += (a - mean_x).powi(2);
+            syy += (b - mean_y).powi(2);
+        }
+        if sxx == 0.0 || syy == 0.0 {
+            return None;
+        }
+        Some((sxy / (sxx * syy).sqrt()).clamp(-1.0, 1.0))
+    }
+
+    // 秩从 1 开始，相同的值取平均秩
+    pub fn ranks(values: &[f64]) -> Vec<f64> {
+        let mut order: Vec<usize> = (0..values.len()).collect();
+        order.sort_by(|&a, &b| values[a].partial_cmp(&values[b]).expect("finite values are ordered"));
+        let mut ranks = vec![0.0; values.len()];
+        let mut start = 0;
+        while start < order.len() {
+            let end = start + order[start..].iter().take_while(|&&i| values[i] == values[order[start]]).count();
+            let rank = (start + end + 1) as f64 / 2.0;
+            for &i in &order[start..end] {
+                ranks[i] = rank;
+            }
+            start = end;
+        }
+        ranks
+    }
+
+    // Spearman 相关系数即秩的 Pearson 相关系数
+    pub fn spearman(x: &[f64], y: &[f64]) -> Option<f64> {
+        pearson(&ranks(x), &ranks(y))
+    }
+
+    pub fn matrix(columns: Vec<String>, rows: &[Vec<f64>]) -> Result<CorrelationMatrix, AnalysisError> {
+        if columns.len() < 2 {
+            return Err(AnalysisError::new("At least two numeric columns are required"));
+        }
+        if rows.len() < 2 {
+            return Err(AnalysisError::new("At least two complete rows are required"));
+        }
+        let series: Vec<Vec<f64>> = (0..columns.len()).map(|c| rows.iter().map(|row| row[c]).collect()).collect();
+        let ranked: Vec<Vec<f64>> = series.iter().map(|s| ranks(s)).collect();
+        let square = |values: &[Vec<f64>]| -> Vec<Vec<Option<f64>>> {
+            (0..values.len())
+                .map(|i| (0..values.len()).map(|j| pearson(&values[i], &values[j])).collect())
+                .collect()
+        };
+        Ok(CorrelationMatrix {
+            columns,
+            row_count: rows.len(),
+            pearson: square(&series),
+            spearman: square(&ranked),
+        })
+    }
+}
+
+// 普通最小二乘线性回归
+mod regression {
+    use super::*;
+
+    // Gauss-Jordan 消元求逆矩阵（列主元），矩阵奇异时返回 None
+    fn invert(mut matrix: Vec<Vec<f64>>) -> Option<Vec<Vec<f64>>> {
+        let n = matrix.len();
+        let mut inverse: Vec<Vec<f64>> = (0..n).map(|i| (0..n).map(|j| if i == j { 1.0 } else { 0.0 }).collect()).collect();
+        let scale = matrix.iter().flatten().fold(0.0f64, |m, v| m.max(v.abs())).max(1.0);
+        for col in 0..n {
+            let pivot = (col..n).max_by(|&a, &b| matrix[a][col].abs().total_cmp(&matrix[b][col].abs()))?;
+            if matrix[pivot][col].abs() <= scale * 1e-12 {
+                return None;
+            }
+            matrix.swap(col, pivot);
+            inverse.swap(col, pivot);
+            let p = matrix[col][col];
+            for j in 0..n {
+                matrix[col][j] /= p;
+                inverse[col][j] /= p;
+            }
+            for row in 0..n {
+                if row != col {
+                    let factor = matrix[row][col];
+                    if factor != 0.0 {
+                        for j in 0..n {
+                            matrix[row][j] -= factor * matrix[col][j];
+                            inverse[row][j] -= factor * inverse[col][j];
+                        }
+                    }
+                }
+            }
+        }
+        Some(inverse)
+    }
+
+    // ln Γ(x)，Lanczos 近似
+    fn ln_gamma(x: f64) -> f64 {
+        const COEFFICIENTS: [f64; 6] = [
+            76.18009172947146,
+            -86.50532032941677,
+            24.01409824083091,
+            -1.231739572450155,
+            0.1208650973866179e-2,
+            -0.5395239384953e-5,
+        ];
+        let tmp = x + 5.5;
+        let tmp = tmp - (x + 0.5) * tmp.ln();
+        let series = COEFFICIENTS
+            .iter()
+            .enumerate()
+            .fold(1.000000000190015, |sum, (i, c)| sum + c / (x + 1.0 + i as f64));
+        -tmp + (2.5066282746310005 * series / x).ln()
+    }
+
+    // 不完全 Beta 函数的连分式展开
+    fn beta_continued_fraction(a: f64, b: f64, x: f64) -> f64 {
+        const TINY: f64 = 1e-300;
+        let (qab, qap, qam) = (a + b, a + 1.0, a - 1.0);
+        let mut c = 1.0;
+        let mut d = 1.0 - qab * x / qap;
+        if d.abs() < TINY {
+            d = TINY;
+        }
+        d = 1.0 / d;
+        let mut h = d;
+        for m in 1..=200 {
+            let m = m as f64;
+            let m2 = 2.0 * m;
+            let aa = m * (b - m) * x / ((qam + m2) * (a + m2));
+            d = 1.0 + aa * d;
+            d = if d.abs() < TINY { TINY } else { d };
+            c = 1.0 + aa / c;
+            c = if c.abs() < TINY { TINY } else { c };
+            d = 1.0 / d;
+            h *= d * c;
+            let aa = -(a + m) * (qab + m) * x / ((a + m2) * (qap + m2));
+            d = 1.0 + aa * d;
+            d = if d.abs() < TINY { TINY } else { d };
+            c = 1.0 + aa / c;
+            c = if c.abs() < TINY { TINY } else { c };
+            d = 1.0 / d;
+            let delta = d * c;
+            h *= delta;
+            if (delta - 1.0).abs() < 1e-14 {
+                break;
+            }
+        }
+        h
+    }
+
+    // 正则化不完全 Beta 函数 I_x(a, b)
+    fn incomplete_beta(a: f64, b: f64, x: f64) -> f64 {
+        if x <= 0.0 {
+            return 0.0;
+        }
+        if x >= 1.0 {
+            return 1.0;
+        }
+        let front = (ln_gamma(a + b) - ln_gamma(a) - ln_gamma(b) + a * x.ln() + b * (1.0 - x).ln()).exp();
+        if x < (a + 1.0) / (a + b + 2.0) {
+            front * beta_continued_fraction(a, b, x) / a
+        } else {
+            1.0 - front * beta_continued_fraction(b, a, 1.0 - x) / b
+        }
+    }
+
+    // 自由度为 df 的 t 分布的双侧 p 值
+    pub fn t_test_p_value(t: f64, df: f64) -> f64 {
+        if t.is_infinite() {
+            return 0.0;
+        }
+        incomplete_beta(df / 2.0, 0.5, df / (df + t * t))
+    }
+
+    // columns[0] 为因变量，其余为自变量；自动加入截距项
+    pub fn ols(columns: Vec<String>, rows: &[Vec<f64>]) -> Result<RegressionResult, AnalysisError> {
+        let features = columns.len() - 1;
+        if features == 0 {
+            return Err(AnalysisError::new("At least one numeric feature column is required"));
+        }
+        let parameters = features + 1;
+        if rows.len() <= parameters {
+            return Err(AnalysisError::new(&format!(
+                "At least {} complete rows are required to fit {} coefficients",
+                parameters + 1,
+                parameters
+            )));
+        }
+
+        // 设计矩阵的第一列为常数 1
+        let design: Vec<Vec<f64>> = rows
+            .iter()
+            .map(|row| std::iter::once(1.0).chain(row[1..].iter().copied()).collect())
+            .collect();
+        let y: Vec<f64> = rows.iter().map(|row| row[0]).collect();
+
+        let mut xtx = vec![vec![0.0; parameters]; parameters];
+        let mut xty = vec![0.0; parameters];
+        for (x, &target) in design.iter().zip(&y) {
+            for i in 0..parameters {
+                xty[i] += x[i] * target;
+                for j in 0..parameters {
+                    xtx[i][j] += x[i] * x[j];
+                }
+            }
+        }
+        let inverse = invert(xtx)
+            .ok_or_else(|| AnalysisError::new("Feature columns are collinear or constant; the regression has no unique solution"))?;
+        let beta: Vec<f64> = inverse.iter().map(|row| row.iter().zip(&xty).map(|(a, b)| a * b).sum()).collect();
+
+        let mut residuals: Vec<f64> = design
+            .iter()
+            .zip(&y)
+            .map(|(x, &target)| target - x.iter().zip(&beta).map(|(a, b)| a * b).sum::<f64>())
+            .collect();
+        let n = rows.len() as f64;
+        let df = rows.len() - parameters;
+        let mut sse: f64 = residuals.iter().map(|r| r * r).sum();
+        // 残差只剩舍入误差时视为完全拟合，否则标准误会是接近 0 的噪声，t 值随之失真
+        if sse <= f64::EPSILON * y.iter().map(|v| v * v).sum::<f64>() {
+            sse = 0.0;
+        }
+        let mean_y = statistics::mean(&y);
+        let sst: f64 = y.iter().map(|v| (v - mean_y).powi(2)).sum();
+        let r_squared = if sst > 0.0 { 1.0 - sse / sst } else { 1.0 };
+        let adjusted_r_squared = 1.0 - (1.0 - r_squared) * (n - 1.0) / df as f64;
+        let sigma2 = sse / df as f64;
+
+        // 完全拟合时，小于此值的估计视为舍入误差产生的 0
+        let zero_estimate = f64::EPSILON.sqrt() * beta.iter().fold(1.0f64, |m, b| m.max(b.abs()));
+        let names = std::iter::once("(intercept)".to_string()).chain(columns[1..].iter().cloned());
+        let coefficients = names
+            .zip(&beta)
+            .enumerate()
+            .map(|(i, (name, &estimate))| {
+                let std_error = (sigma2 * inverse[i][i]).max(0.0).sqrt();
+                if std_error == 0.0 {
+                    let p_value = if estimate.abs() <= zero_estimate { 1.0 } else { 0.0 };
+                    return Coefficient { name, estimate, std_error, t_value: None, p_value };
+                }
+                let t_value = estimate / std_error;
+                Coefficient { name, estimate, std_error, t_value: Some(t_value), p_value: t_test_p_value(t_value, df as f64) }
+            })
+            .collect();
+
+        residuals.sort_by(|a, b| a.partial_cmp(b).expect("finite values are ordered"));
+        Ok(RegressionResult {
+            target: columns[0].clone(),
+            row_count: rows.len(),
+            degrees_of_freedom: df,
+            coefficients,
+            r_squared,
+            adjusted_r_squared,
+            residuals: ResidualStats {
+                min: residuals[0],
+                q1: statistics::percentile(&residuals, 25.0),
+                median: statistics::median(&residuals),
+                q3: statistics::percentile(&residuals, 75.0),
+                max: residuals[residuals.len() - 1],
+                standard_error: sigma2.sqrt(),
+            },
+        })
+    }
+}
+
+// 时间序列：重采样、移动平均、STL 风格分解与自相关
+mod timeseries {
+    use super::*;
+    use chrono::{Duration, NaiveDate, NaiveDateTime};
+
+    // 重采样最多生成的时间点数，防止过小的间隔耗尽内存
+    const MAX_POINTS: usize = 1_000_000;
+    // 周期子序列平滑使用的 LOESS 窗口
+    const SEASONAL_SPAN: usize = 7;
+    // 重采样间隔上限（约 100 年），使生成的时间戳都能表示
+    const MAX_INTERVAL_SECS: i64 = 100 * 365 * 86_400;
+    // 分解的趋势 LOESS 代价约为 点数 × 周期，超过此值时拒绝
+    const MAX_DECOMPOSITION_WORK: usize = 10_000_000;
+
+    pub struct TimeSeriesOptions {
+        pub interval: Option<i64>,
+        pub fill: FillStrategy,
+        pub window: usize,
+        pub alpha: Option<f64>,
+        pub period: Option<usize>,
+        pub lags: Option<Vec<usize>>,
+    }
+
+    // 解析 "30s"、"15m"、"1h"、"1d"、"1w" 形式的间隔，返回秒数
+    pub fn parse_interval(text: &str) -> Result<i64, AnalysisError> {
+        let text = text.trim();
+        let split = text.find(|c: char| !c.is_ascii_digit()).unwrap_or(text.len());
+        let (amount, unit) = text.split_at(split);
+        let unit_secs = match unit {
+            "s" => 1,
+            "m" => 60,
+            "h" => 3600,
+            "d" => 86_400,
+            "w" => 604_800,
+            _ => 0,
+        };
+        match amount.parse::<i64>() {
+            Ok(amount) if amount > 0 && unit_secs > 0 => amount
+                .checked_mul(unit_secs)
+                .filter(|&secs| secs <= MAX_INTERVAL_SECS)
+                .ok_or_else(|| AnalysisError::new("interval must be at most 100 years")),
+            _ => Err(AnalysisError::new("interval must look like 30s, 15m, 1h, 1d or 1w")),
+        }
+    }
+
+    pub fn parse_lags(text: &str) -> Result<Vec<usize>, AnalysisError> {
+        text.split(',')
+            .map(|lag| lag.trim().parse::<usize>().ok().filter(|&lag| lag > 0))
+            .collect::<Option<Vec<_>>>()
+            .ok_or_else(|| AnalysisError::new("lags must be positive integers separated by commas"))
+    }
+
+    fn epoch() -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(1970, 1, 1).and_then(|d| d.and_hms_opt(0, 0, 0)).expect("valid epoch")
+    }
+
+    // 把 (秒, 值) 按间隔分桶，桶内取平均；没有数据的桶为 None
+    fn resample(points: &[(i64, f64)], interval: i64) -> Result<(i64, Vec<Option<f64>>), AnalysisError> {
+        let start = points[0].0.div_euclid(interval) * interval;
+        let buckets = ((points[points.len() - 1].0 - start) / interval) as usize + 1;
+        if buckets > MAX_POINTS {
+            return Err(AnalysisError::new(&format!(
+                "Resampling would produce {} points; choose a larger interval",
+                buckets
+            )));
+        }
+        let mut sums = vec![(0.0, 0usize); buckets];
+        for &(time, value) in points {
+            let bucket = &mut sums[((time - start) / interval) as usize];
+            bucket.0 += value;
+            bucket.1 += 1;
+        }
+        let values = sums.into_iter().map(|(sum, count)| (count > 0).then(|| sum / count as f64)).collect();
+        Ok((start, values))
+    }
+
+    // 第一个和最后一个桶一定有数据，所以任何策略都能填满所有空桶
+    fn fill_gaps(values: &[Option<f64>], fill: FillStrategy) -> Vec<f64> {
+        let observed: Vec<f64> = values.iter().flatten().copied().collect();
+        let mean = statistics::mean(&observed);
+        // 每个位置之后（含自身）最近的有数据的桶
+        let mut next = vec![None; values.len()];
+        for i in (0..values.len()).rev() {
+            next[i] = values[i].map(|v| (i, v)).or_else(|| next.get(i + 1).copied().flatten());
+        }
+        let mut previous: Option<(usize, f64)> = None;
+        let mut filled = Vec::with_capacity(values.len());
+        for (i, value) in values.iter().enumerate() {
+            let value = match (value, fill) {
+                (Some(v), _) => {
+                    previous = Some((i, *v));
+                    *v
+                }
+                (None, FillStrategy::Zero) => 0.0,
+                (None, FillStrategy::Mean) => mean,
+                (None, FillStrategy::Forward) => previous.map_or(mean, |(_, v)| v),
+                (None, FillStrategy::Backward) => next[i].map_or(mean, |(_, v)| v),
+                (None, FillStrategy::Linear) => match (previous, next[i]) {
+                    (Some((i0, v0)), Some((i1, v1))) => v0 + (v1 - v0) * (i - i0) as f64 / (i1 - i0) as f64,
+                    _ => mean,
+                },
+            };
+            filled.push(value);
+        }
+        filled
+    }
+
+    // 简单移动平均：最近 window 个值的平均，用滑动窗口的和计算
+    pub fn sma(values: &[f64], window: usize) -> Vec<Option<f64>> {
+        let mut sum = 0.0;
+        (0..values.len())
+            .map(|i| {
+                sum += values[i];
+                if i >= window {
+                    sum -= values[i - window];
+                }
+                (i + 1 >= window).then(|| sum / window as f64)
+            })
+            .collect()
+    }
+
+    // 加权移动平均：窗口内权重依次为 1, 2, ..., window，越近权重越大。
+    // 窗口右移一位时每个旧值的权重减 1，因此加权和减去上一个窗口的和，再加上 window × 新值
+    pub fn wma(values: &[f64], window: usize) -> Vec<Option<f64>> {
+        let w = window as f64;
+        let total = w * (w + 1.0) / 2.0;
+        let (mut weighted, mut sum) = (0.0, 0.0);
+        (0..values.len())
+            .map(|i| {
+                weighted += w * values[i] - sum;
+                sum += values[i];
+                if i >= window {
+                    sum -= values[i - window];
+                }
+                (i + 1 >= window).then(|| weighted / total)
+            })
+            .collect()
+    }
+
+    // 指数移动平均，以第一个值为初始值
+    pub fn ema(values: &[f64], alpha: f64) -> Vec<f64> {
+        let mut current = values[0];
+        values
+            .iter()
+            .map(|v| {
+                current = alpha * v + (1.0 - alpha) * current;
+                current
+            })
+            .collect()
+    }
+
+    // 局部线性 LOESS：每个点取最近的 span 个点，以 tricube 权重做加权线性拟合
+    fn loess(values: &[f64], span: usize) -> Vec<f64> {
+        let n = values.len();
+        if n < 3 {
+            return values.to_vec();
+        }
+        let span = span.clamp(3, n);
+        (0..n)
+            .map(|i| {
+                let start = i.saturating_sub(span / 2).min(n - span);
+                let end = start + span;
+                let max_distance = (i - start).max(end - 1 - i) as f64 + 1.0;
+                let (mut sw, mut swx, mut swy, mut swxx, mut swxy) = (0.0, 0.0, 0.0, 0.0, 0.0);
+                for (j, &y) in values.iter().enumerate().take(end).skip(start) {
+                    let x = j as f64 - i as f64;
+                    let w = (1.0 - (x.abs() / max_distance).powi(3)).powi(3);
+                    sw += w;
+                    swx += w * x;
+                    swy += w * y;
+                    swxx += w * x * x;
+                    swxy += w * x * y;
+                }
+                let denominator = sw * swxx - swx * swx;
+                if denominator.abs() < 1e-12 {
+                    return swy / sw;
+                }
+                // x 以 i 为原点，因此拟合直线在 i 处的值就是截距
+                let slope = (sw * swxy - swx * swy) / denominator;
+                (swy - slope * swx) / sw
+            })
+            .collect()
+    }
+
+    // 居中移动平均，窗口为偶数时两端各取一半权重（2×window 移动平均），边缘处窗口截断。
+    // 用前缀和计算，与窗口大小无关
+    fn centered_mean(values: &[f64], window: usize) -> Vec<f64> {
+        let n = values.len();
+        let half = window / 2;
+        let end_weight = if window % 2 == 0 { 0.5 } else { 1.0 };
+        let mut prefix = vec![0.0; n + 1];
+        for (i, value) in values.iter().enumerate() {
+            prefix[i + 1] = prefix[i] + value;
+        }
+        (0..n)
+            .map(|i| {
+                let start = i.saturating_sub(half);
+                let end = (i + half + 1).min(n);
+                let mut sum = prefix[end] - prefix[start];
+                let mut weight = (end - start) as f64;
+                // 恰好位于窗口两端的值只按端点权重计入
+                for j in [i.checked_sub(half), Some(i + half).filter(|&j| j < n)].into_iter().flatten() {
+                    sum -= (1.0 - end_weight) * values[j];
+                    weight -= 1.0 - end_weight;
+                }
+                sum / weight
+            })
+            .collect()
+    }
+
+    fn strength(component: &[f64], residual: &[f64]) -> f64 {
+        let combined: Vec<f64> = component.iter().zip(residual).map(|(c, r)| c + r).collect();
+        let combined_variance = statistics::variance(&combined, statistics::mean(&combined));
+        if combined_variance == 0.0 {
+            return 0.0;
+        }
+        (1.0 - statistics::variance(residual, statistics::mean(residual)) / combined_variance).max(0.0)
+    }
+
+    // 简化的 STL：交替地用周期子序列 LOESS 估计季节项、用 LOESS 估计趋势项
+    pub fn decompose(values: &[f64], period: usize) -> Result<Decomposition, AnalysisError> {
+        let n = values.len();
+        if period < 2 || n < 2 * period {
+            return Err(AnalysisError::new("Decomposition needs a period of at least 2 and two full periods of data"));
+        }
+        if n.saturating_mul(period) > MAX_DECOMPOSITION_WORK {
+            return Err(AnalysisError::new(&format!(
+                "A period of {} is too long for {} points; choose a larger interval or a shorter period",
+                period, n
+            )));
+        }
+        let trend_span = {
+            let span = (1.5 * period as f64 / (1.0 - 1.5 / SEASONAL_SPAN as f64)).ceil() as usize;
+            span | 1
+        };
+        let mut trend = vec![0.0; n];
+        let mut seasonal = vec![0.0; n];
+        for _ in 0..2 {
+            let detrended: Vec<f64> = values.iter().zip(&trend).map(|(v, t)| v - t).collect();
+            let mut cycle = vec![0.0; n];
+            for phase in 0..period {
+                let indices: Vec<usize> = (phase..n).step_by(period).collect();
+                let subseries: Vec<f64> = indices.iter().map(|&i| detrended[i]).collect();
+                for (&i, smoothed) in indices.iter().zip(loess(&subseries, SEASONAL_SPAN)) {
+                    cycle[i] = smoothed;
+                }
+            }
+            // 去掉周期项中的低频成分，使季节项围绕 0 波动
+            let low_pass = centered_mean(&cycle, period);
+            seasonal = cycle.iter().zip(&low_pass).map(|(c, l)| c - l).collect();
+            let deseasonalized: Vec<f64> = values.iter().zip(&seasonal).map(|(v, s)| v - s).collect();
+            trend = loess(&deseasonalized, trend_span);
+        }
+        let residual: Vec<f64> = values.iter().zip(&trend).zip(&seasonal).map(|((v, t), s)| v - t - s).collect();
+        Ok(Decomposition {
+            period,
+            trend_strength: strength(&trend, &residual),
+            seasonal_strength: strength(&seasonal, &residual),
+            trend,
+            seasonal,
+            residual,
+        })
+    }
+
+    pub fn autocorrelation(values: &[f64], lag: usize) -> f64 {
+        let mean = statistics::mean(values);
+        let denominator: f64 = values.iter().map(|v| (v - mean).powi(2)).sum();
+        if denominator == 0.0 {
+            return 0.0;
+        }
+        let numerator: f64 = values.iter().zip(&values[lag..]).map(|(a, b)| (a - mean) * (b - mean)).sum();
+        numerator / denominator
+    }
+
+    pub fn analyze(
+        table: &table::Table,
+        time_column: Option<&str>,
+        value_column: Option<&str>,
+        options: &TimeSeriesOptions,
+    ) -> Result<TimeSeriesAnalysis, AnalysisError> {
+        let time_index = match time_column {
+            Some(name) => table.column_index(name)?,
+            None => table
+                .first_column_of(ColumnType::Datetime)
+                .ok_or_else(|| AnalysisError::new("No timestamp column found; set time_column"))?,
+        };
+        let value_index = match value_column {
+            Some(name) => table.column_index(name)?,
+            None => table
+                .first_column_of(ColumnType::Numeric)
+                .ok_or_else(|| AnalysisError::new("No numeric column found; set value_column"))?,
+        };
+        if options.window == 0 {
+            return Err(AnalysisError::new("window must be at least 1"));
+        }
+        let alpha = options.alpha.unwrap_or(2.0 / (options.window as f64 + 1.0));
+        if !(alpha > 0.0 && alpha <= 1.0) {
+            return Err(AnalysisError::new("alpha must be in (0, 1]"));
+        }
+
+        // 时间或数值为空的行跳过，其余必须能解析
+        let mut points: Vec<(i64, f64)> = Vec::new();
+        for (row, (time, value)) in table.column(time_index).zip(table.column(value_index)).enumerate() {
+            if table::is_null(time) || table::is_null(value) {
+                continue;
+            }
+            let time = table::parse_datetime(time)
+                .ok_or_else(|| AnalysisError::new(&format!("Invalid timestamp in row {}: {}", row + 1, time)))?;
+            let value = table::parse_number(value)
+                .ok_or_else(|| AnalysisError::new(&format!("Invalid number in row {}: {}", row + 1, value)))?;
+            points.push(((time - epoch()).num_seconds(), value));
+        }
+        if points.is_empty() {
+            return Err(AnalysisError::new("No data to analyze"));
+        }
+        points.sort_by_key(|&(time, _)| time);
+
+        let interval = match options.interval {
+            Some(interval) => interval,
+            None => {
+                let mut gaps: Vec<f64> =
+                    points.windows(2).map(|w| (w[1].0 - w[0].0) as f64).filter(|&gap| gap > 0.0).collect();
+                if gaps.is_empty() {
+                    86_400
+                } else {
+                    gaps.sort_by(|a, b| a.partial_cmp(b).expect("finite values are ordered"));
+                    statistics::median(&gaps).round() as i64
+                }
+            }
+        };
+        let (start, resampled) = resample(&points, interval)?;
+        let values = fill_gaps(&resampled, options.fill);
+        if options.window > values.len() {
+            return Err(AnalysisError::new(&format!(
+                "window {} exceeds the series length {}",
+                options.window,
+                values.len()
+            )));
+        }
+
+        let sma = sma(&values, options.window);
+        let wma = wma(&values, options.window);
+        let ema = ema(&values, alpha);
+        let points = (0..values.len())
+            .map(|i| TimeSeriesPoint {
+                timestamp: (epoch() + Duration::seconds(start + i as i64 * interval))
+                    .format("%Y-%m-%dT%H:%M:%S")
+                    .to_string(),
+                value: values[i],
+                observed: resampled[i].is_some(),
+                sma: sma[i],
+                ema: ema[i],
+                wma: wma[i],
+            })
+            .collect();
+
+        let decomposition = options.period.map(|period| decompose(&values, period)).transpose()?;
+        let lags = options.lags.clone().unwrap_or_else(|| (1..=10.min(values.len().saturating_sub(1))).collect());
+        if let Some(&lag) = lags.iter().find(|&&lag| lag >= values.len()) {
+            return Err(AnalysisError::new(&format!("Lag {} exceeds the series length {}", lag, values.len())));
+        }
+        let observed_count = resampled.iter().filter(|v| v.is_some()).count();
+        Ok(TimeSeriesAnalysis {
+            time_column: table.headers[time_index].clone(),
+            value_column: table.headers[value_index].clone(),
+            interval_secs: interval,
+            fill: options.fill,
+            observed_count,
+            filled_count: values.len() - observed_count,
+            points,
+            decomposition,
+            autocorrelation: lags.into_iter().map(|lag| Autocorrelation { lag, value: autocorrelation(&values, lag) }).collect(),
+            autocorrelation_bound: 1.96 / (values.len() as f64).sqrt(),
+        })
+    }
+}
+
+// 分组聚合与透视表
+mod aggregate {
+    use super::*;
+    use std::collections::{BTreeMap, BTreeSet};
+
+    // 透视表的列键组合上限，避免高基数的列键生成过宽的表
+    const MAX_PIVOT_COLUMNS: usize = 1000;
+
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub enum Function {
+        Sum,
+        Mean,
+        Count,
+        Min,
+        Max,
+        Percentile(f64),
+    }
+
+    // 一个聚合：函数加列名，count(*) 的列为 None
+    #[derive(Debug, Clone)]
+    pub struct Aggregate {
+        pub function: Function,
+        pub column: Option<String>,
+    }
+
+    impl Aggregate {
+        // 解析 "sum(sales)"、"count(*)"、"median(price)"、"p95(latency)"
+        pub fn parse(text: &str) -> Result<Aggregate, AnalysisError> {
+            let invalid = || AnalysisError::new(&format!("Invalid aggregate: {}", text.trim()));
+            let (name, rest) = text.trim().split_once('(').ok_or_else(invalid)?;
+            let column = rest.strip_suffix(')').ok_or_else(invalid)?.trim();
+            let function = match name.trim() {
+                "sum" => Function::Sum,
+                "mean" | "avg" => Function::Mean,
+                "count" => Function::Count,
+                "min" => Function::Min,
+                "max" => Function::Max,
+                "median" => Function::Percentile(50.0),
+                name => name
+                    .strip_prefix('p')
+                    .and_then(|p| p.parse::<f64>().ok())
+                    .filter(|p| (0.0..=100.0).contains(p))
+                    .map(Function::Percentile)
+                    .ok_or_else(invalid)?,
+            };
+            let column = match column {
+                "" | "*" if function == Function::Count => None,
+                "" | "*" => return Err(invalid()),
+                column => Some(column.to_string()),
+            };
+            Ok(Aggregate { function, column })
+        }
+
+        pub fn parse_list(text: &str) -> Result<Vec<Aggregate>, AnalysisError> {
+            let aggregates = text
+                .split(',')
+                .filter(|part| !part.trim().is_empty())
+                .map(Aggregate::parse)
+                .collect::<Result<Vec<_>, _>>()?;
+            if aggregates.is_empty() {
+                return Err(AnalysisError::new("At least one aggregate is required"));
+            }
+            Ok(aggregates)
+        }
+
+        pub fn label(&self) -> String {
+            let column = self.column.as_deref().unwrap_or("*");
+            match self.function {
+                Function::Sum => format!("sum({})", column),
+                Function::Mean => format!("mean({})", column),
+                Function::Count => format!("count({})", column),
+                Function::Min => format!("min({})", column),
+                Function::Max => format!("max({})", column),
+                Function::Percentile(p) => format!("p{}({})", p, column),
+            }
+        }
+
+        // 找到聚合的列；除 count 外都要求是数值列
+        fn resolve(&self, table: &table::Table) -> Result<Option<usize>, AnalysisError> {
+            let Some(column) = &self.column else {
+                return Ok(None);
+            };
+            let index = table.column_index(column)?;
+            if self.function != Function::Count && table::infer_type(table.column(index)) != ColumnType::Numeric {
+                return Err(AnalysisError::new(&format!("Column {} is not numeric", column)));
+            }
+            Ok(Some(index))
+        }
+
+        fn apply(&self, table: &table::Table, column: Option<usize>, rows: &[usize]) -> Option<f64> {
+            let Some(column) = column else {
+                return Some(rows.len() as f64);
+            };
+            let cells = rows.iter().map(|&row| table.rows[row].get(column).map(String::as_str).unwrap_or(""));
+            if self.function == Function::Count {
+                return Some(cells.filter(|cell| !table::is_null(cell)).count() as f64);
+            }
+            let mut values: Vec<f64> = cells.filter_map(table::parse_number).collect();
+            if values.is_empty() {
+                return None;
+            }
+            Some(match self.function {
+                Function::Sum => values.iter().sum(),
+                Function::Mean => statistics::mean(&values),
+                Function::Min => values.iter().copied().fold(f64::INFINITY, f64::min),
+                Function::Max => values.iter().copied().fold(f64::NEG_INFINITY, f64::max),
+                Function::Percentile(p) => {
+                    values.sort_by(|a, b| a.partial_cmp(b).expect("finite values are ordered"));
+                    statistics::percentile(&values, p)
+                }
+                Function::Count => unreachable!("count is handled above"),
+            })
+        }
+    }
+
+    fn key_indices(table: &table::Table, names: &[String]) -> Result<Vec<usize>, AnalysisError> {
+        names.iter().map(|name| table.column_index(name)).collect()
+    }
+
+    fn key_of(table: &table::Table, row: usize, indices: &[usize]) -> Vec<String> {
+        indices
+            .iter()
+            .map(|&index| table.rows[row].get(index).map(|cell| cell.trim().to_string()).unwrap_or_default())
+            .collect()
+    }
+
+    // 分组按键排序，空单元格作为空字符串参与分组
+    pub fn group_by(table: &table::Table, keys: &[String], aggregates: &[Aggregate]) -> Result<GroupByResult, AnalysisError> {
+        if keys.is_empty() {
+            return Err(AnalysisError::new("At least one group-by column is required"));
+        }
+        let indices = key_indices(table, keys)?;
+        let columns = aggregates.iter().map(|a| a.resolve(table)).collect::<Result<Vec<_>, _>>()?;
+        let mut groups: BTreeMap<Vec<String>, Vec<usize>> = BTreeMap::new();
+        for row in 0..table.rows.len() {
+            groups.entry(key_of(table, row, &indices)).or_default().push(row);
+        }
+        Ok(GroupByResult {
+            keys: keys.to_vec(),
+            aggregates: aggregates.iter().map(Aggregate::label).collect(),
+            groups: groups
+                .into_iter()
+                .map(|(key, rows)| GroupRow {
+                    key,
+                    values: aggregates.iter().zip(&columns).map(|(a, &column)| a.apply(table, column, &rows)).collect(),
+                })
+                .collect(),
+        })
+    }
+
+    pub fn pivot(
+        table: &table::Table,
+        row_keys: &[String],
+        column_keys: &[String],
+        aggregate: &Aggregate,
+    ) -> Result<PivotTable, AnalysisError> {
+        if row_keys.is_empty() || column_keys.is_empty() {
+            return Err(AnalysisError::new("Both row and column keys are required"));
+        }
+        let row_indices = key_indices(table, row_keys)?;
+        let column_indices = key_indices(table, column_keys)?;
+        let column = aggregate.resolve(table)?;
+
+        let mut cells: BTreeMap<Vec<String>, BTreeMap<Vec<String>, Vec<usize>>> = BTreeMap::new();
+        let mut columns: BTreeSet<Vec<String>> = BTreeSet::new();
+        for row in 0..table.rows.len() {
+            let column_key = key_of(table, row, &column_indices);
+            columns.insert(column_key.clone());
+            if columns.len() > MAX_PIVOT_COLUMNS {
+                return Err(AnalysisError::new(&format!(
+                    "The pivot would have more than {} columns",
+                    MAX_PIVOT_COLUMNS
+                )));
+            }
+            cells
+                .entry(key_of(table, row, &row_indices))
+                .or_default()
+                .entry(column_key)
+                .or_default()
+                .push(row);
+        }
+
+        // 没有任何行的单元格，count 为 0，其余聚合为 null
+        let empty = match aggregate.function {
+            Function::Count => Some(0.0),
+            _ => None,
+        };
+        let rows = cells
+            .into_iter()
+            .map(|(key, by_column)| GroupRow {
+                key,
+                values: columns
+                    .iter()
+                    .map(|c| by_column.get(c).map_or(empty, |rows| aggregate.apply(table, column, rows)))
+                    .collect(),
+            })
+            .collect();
+        Ok(PivotTable {
+            row_keys: row_keys.to_vec(),
+            column_keys: column_keys.to_vec(),
+            aggregate: aggregate.label(),
+            columns: columns.into_iter().collect(),
+            rows,
+        })
+    }
+
+    fn write_csv(header: Vec<String>, rows: &[GroupRow]) -> Result<String, AnalysisError> {
+        let failed = |e: csv::Error| AnalysisError::new(&format!("Failed to write CSV: {}", e));
+        let mut writer = csv::Writer::from_writer(Vec::new());
+        writer.write_record(&header).map_err(failed)?;
+        for row in rows {
+            let values = row.values.iter().map(|v| v.map(|v| v.to_string()).unwrap_or_default());
+            writer.write_record(row.key.iter().cloned().chain(values)).map_err(failed)?;
+        }
+        let bytes = writer.into_inner().map_err(|e| AnalysisError::new(&format!("Failed to write CSV: {}", e)))?;
+        String::from_utf8(bytes).map_err(|e| AnalysisError::new(&e.to_string()))
+    }
+
+    pub fn group_by_csv(result: &GroupByResult) -> Result<String, AnalysisError> {
+        write_csv(result.keys.iter().chain(&result.aggregates).cloned().collect(), &result.groups)
+    }
+
+    // 多个列键的组合以 " | " 连接作为 CSV 列名
+    pub fn pivot_csv(result: &PivotTable) -> Result<String, AnalysisError> {
+        let header = result
+            .row_keys
+            .iter()
+            .cloned()
+            .chain(result.columns.iter().map(|c| c.join(" | ")))
+            .collect();
+        write_csv(header, &result.rows)
+    }
+}
+
+// 离群值检测
+mod outliers {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    // 孤立森林默认使用固定种子，使同一份数据的结果可复现
+    pub const DEFAULT_SEED: u64 = 42;
+    const TREES: usize = 100;
+    const SAMPLE_SIZE: usize = 256;
+
+    // 从排好序的数据计算上下界，孤立森林没有固定的上下界
+    fn fences(sorted: &[f64], method: OutlierMethod, threshold: f64) -> Option<(f64, f64)> {
+        match method {
+            OutlierMethod::ZScore => {
+                let mean = statistics::mean(sorted);
+                let std_dev = statistics::variance(sorted, mean).sqrt();
+                Some((mean - threshold * std_dev, mean + threshold * std_dev))
+            }
+            OutlierMethod::ModifiedZScore => {
+                let median = statistics::median(sorted);
+                let mad = median_absolute_deviation(sorted, median);
+                Some((median - threshold * mad / 0.6745, median + threshold * mad / 0.6745))
+            }
+            OutlierMethod::Iqr => {
+                let q1 = statistics::percentile(sorted, 25.0);
+                let q3 = statistics::percentile(sorted, 75.0);
+                Some((q1 - threshold * (q3 - q1), q3 + threshold * (q3 - q1)))
+            }
+            OutlierMethod::IsolationForest => None,
+        }
+    }
+
+    // 以 spread 为单位的偏离程度；spread 为 0 时上下界重合，任何偏离都在界外，分数为无穷大
+    fn scaled(distance: f64, spread: f64) -> f64 {
+        if spread > 0.0 {
+            distance / spread
+        } else if distance == 0.0 {
+            0.0
+        } else {
+            distance.signum() * f64::INFINITY
+        }
+    }
+
+    fn median_absolute_deviation(sorted: &[f64], median: f64) -> f64 {
+        let mut deviations: Vec<f64> = sorted.iter().map(|v| (v - median).abs()).collect();
+        deviations.sort_by(|a, b| a.partial_cmp(b).expect("finite values are ordered"));
+        statistics::median(&deviations)
+    }
+
+    // 二叉搜索树中一次不成功查找的平均路径长度，用于归一化
+    fn average_path_length(n: usize) -> f64 {
+        match n {
+            0 | 1 => 0.0,
+            2 => 1.0,
+            _ => {
+                let n = n as f64;
+                2.0 * ((n - 1.0).ln() + 0.5772156649) - 2.0 * (n - 1.0) / n
+            }
+        }
+    }
+
+    // 孤立树：在样本的最小值和最大值之间随机选取划分点，直到样本无法再分或达到最大深度
+    enum IsolationTree {
+        Leaf { size: usize },
+        Split { at: f64, left: Box<IsolationTree>, right: Box<IsolationTree> },
+    }
+
+    impl IsolationTree {
+        fn build(sample: &[f64], depth: usize, max_depth: usize, rng: &mut StdRng) -> IsolationTree {
+            let (min, max) = sample.iter().fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), &v| (lo.min(v), hi.max(v)));
+            if depth >= max_depth || sample.len() <= 1 || min == max {
+                return IsolationTree::Leaf { size: sample.len() };
+            }
+            let at = rng.gen_range(min..max);
+            let (left, right): (Vec<f64>, Vec<f64>) = sample.iter().partition(|&&v| v < at);
+            IsolationTree::Split {
+                at,
+                left: Box::new(IsolationTree::build(&left, depth + 1, max_depth, rng)),
+                right: Box::new(IsolationTree::build(&right, depth + 1, max_depth, rng)),
+            }
+        }
+
+        // 孤立 value 所需的路径长度，叶子中剩余的样本按平均路径长度补偿
+        fn path_length(&self, value: f64) -> f64 {
+            let mut node = self;
+            let mut depth = 0.0;
+            loop {
+                match node {
+                    IsolationTree::Leaf { size } => return depth + average_path_length(*size),
+                    IsolationTree::Split { at, left, right } => {
+                        node = if value < *at { left } else { right };
+                        depth += 1.0;
+                    }
+                }
+            }
+        }
+    }
+
+    // 一维的简化孤立森林，异常分数 s = 2^(-E[h] / c(ψ))，越接近 1 越异常
+    fn isolation_scores(data: &[f64], seed: u64) -> Vec<f64> {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let sample_size = SAMPLE_SIZE.min(data.len());
+        let normalizer = average_path_length(sample_size);
+        if normalizer == 0.0 {
+            return vec![0.5; data.len()];
+        }
+        let max_depth = (sample_size as f64).log2().ceil() as usize;
+        let forest: Vec<IsolationTree> = (0..TREES)
+            .map(|_| {
+                let sample: Vec<f64> = rand::seq::index::sample(&mut rng, data.len(), sample_size)
+                    .into_iter()
+                    .map(|i| data[i])
+                    .collect();
+                IsolationTree::build(&sample, 0, max_depth, &mut rng)
+            })
+            .collect();
+        data.iter()
+            .map(|&value| {
+                let total: f64 = forest.iter().map(|tree| tree.path_length(value)).sum();
+                2f64.powf(-(total / TREES as f64) / normalizer)
+            })
+            .collect()
+    }
+
+    pub fn detect(data: &[f64], method: OutlierMethod, threshold: Option<f64>, seed: u64) -> Result<OutlierReport, AnalysisError> {
+        if data.is_empty() {
+            return Err(AnalysisError::new("No data to analyze"));
+        }
+        if data.iter().any(|v| !v.is_finite()) {
+            return Err(AnalysisError::new("Data contains NaN or infinite values"));
+        }
+        let threshold = threshold.unwrap_or_else(|| method.default_threshold());
+        if !(threshold.is_finite() && threshold >= 0.0) {
+            return Err(AnalysisError::new("threshold must be a non-negative number"));
+        }
+        let mut sorted = data.to_vec();
+        sorted.sort_by(|a, b| a.partial_cmp(b).expect("finite values are ordered"));
+
+        let scores: Vec<f64> = match method {
+            OutlierMethod::ZScore => {
+                let mean = statistics::mean(&sorted);
+                let std_dev = statistics::variance(&sorted, mean).sqrt();
+                data.iter().map(|v| scaled(v - mean, std_dev)).collect()
+            }
+            OutlierMethod::ModifiedZScore => {
+                let median = statistics::median(&sorted);
+                let mad = median_absolute_deviation(&sorted, median);
+                data.iter().map(|v| scaled(0.6745 * (v - median), mad)).collect()
+            }
+            // 超出四分位的距离，以 IQR 为单位
+            OutlierMethod::Iqr => {
+                let q1 = statistics::percentile(&sorted, 25.0);
+                let q3 = statistics::percentile(&sorted, 75.0);
+                let iqr = q3 - q1;
+                data.iter()
+                    .map(|&v| {
+                        let distance = if v < q1 { v - q1 } else if v > q3 { v - q3 } else { 0.0 };
+                        scaled(distance, iqr)
+                    })
+                    .collect()
+            }
+            OutlierMethod::IsolationForest => isolation_scores(data, seed),
+        };
+
+        let outliers: Vec<Outlier> = data
+            .iter()
+            .zip(&scores)
+            .enumerate()
+            .filter(|(_, (_, score))| score.abs() > threshold)
+            .map(|(index, (&value, &score))| Outlier { row: index + 1, value, score })
+            .collect();
+        let bounds = fences(&sorted, method, threshold);
+        Ok(OutlierReport {
+            method,
+            threshold,
+            total_count: data.len(),
+            outlier_count: outliers.len(),
+            lower_fence: bounds.map(|(lower, _)| lower),
+            upper_fence: bounds.map(|(_, upper)| upper),
+            outliers,
+        })
+    }
+}
+
+// 固定内存的流式统计：Welford 计算均值方差，t-digest 估算百分位，HyperLogLog 估算不同值个数
+mod streaming {
+    use super::*;
+    use std::collections::hash_map::DefaultHasher;
+    use std::f64::consts::PI;
+    use std::hash::{Hash, Hasher};
+
+    // t-digest 的压缩参数，越大越精确，质心数约为其两倍
+    const COMPRESSION: f64 = 100.0;
+    // HyperLogLog 的精度，共 2^14 个寄存器（16 KiB）
+    const HLL_PRECISION: u32 = 14;
+
+    // Welford 在线算法，数值稳定地累计均值与方差
+    #[derive(Default)]
+    pub struct Welford {
+        pub count: u64,
+        pub mean: f64,
+        m2: f64,
+        pub min: f64,
+        pub max: f64,
+    }
+
+    impl Welford {
+        pub fn push(&mut self, value: f64) {
+            if self.count == 0 {
+                self.min = value;
+                self.max = value;
+            }
+            self.count += 1;
+            let delta = value - self.mean;
+            self.mean += delta / self.count as f64;
+            self.m2 += delta * (value - self.mean);
+            self.min = self.min.min(value);
+            self.max = self.max.max(value);
+        }
+
+        // 样本方差，与 AnalysisResult 一致
+        pub fn variance(&self) -> f64 {
+            if self.count < 2 {
+                0.0
+            } else {
+                self.m2 / (self.count - 1) as f64
+            }
+        }
+    }
+
+    #[derive(Clone, Copy)]
+    struct Centroid {
+        mean: f64,
+        weight: f64,
+    }
+
+    // 合并式 t-digest，使用 k1 尺度函数，两端的质心更小因而尾部百分位更精确
+    pub struct TDigest {
+        centroids: Vec<Centroid>,
+        buffer: Vec<f64>,
+        count: f64,
+        min: f64,
+        max: f64,
+    }
+
+    impl TDigest {
+        pub fn new() -> TDigest {
+            TDigest {
+                centroids: Vec::new(),
+                buffer: Vec::with_capacity(Self::buffer_size()),
+                count: 0.0,
+                min: f64::INFINITY,
+                max: f64::NEG_INFINITY,
+            }
+        }
+
+        fn buffer_size() -> usize {
+            (COMPRESSION * 5.0) as usize
+        }
+
+        pub fn push(&mut self, value: f64) {
+            self.buffer.push(value);
+            self.min = self.min.min(value);
+            self.max = self.max.max(value);
+            if self.buffer.len() >= Self::buffer_size() {
+                self.flush();
+            }
+        }
+
+        // 某个累计比例 q 处的质心所能覆盖到的最大累计比例
+        fn q_limit(q: f64) -> f64 {
+            let k = COMPRESSION / (2.0 * PI) * (2.0 * q - 1.0).asin() + 1.0;
+            if k >= COMPRESSION / 4.0 {
+                1.0
+            } else {
+                ((2.0 * PI * k / COMPRESSION).sin() + 1.0) / 2.0
+            }
+        }
+
+        fn flush(&mut self) {
+            if self.buffer.is_empty() {
+                return;
+            }
+            let mut items: Vec<Centroid> = self.centroids.drain(..).collect();
+            items.extend(self.buffer.drain(..).map(|mean| Centroid { mean, weight: 1.0 }));
+            items.sort_by(|a, b| a.mean.partial_cmp(&b.mean).expect("finite values are ordered"));
+            self.count = items.iter().map(|c| c.weight).sum();
+
+            let mut merged = Vec::with_capacity(items.len().min(COMPRESSION as usize * 2));
+            let mut current = items[0];
+            let mut weight_before = 0.0;
+            let mut limit = self.count * Self::q_limit(0.0);
+            for item in items.into_iter().skip(1) {
+                if weight_before + current.weight + item.weight <= limit {
+                    let weight = current.weight + item.weight;
+                    current.mean += (item.mean - current.mean) * item.weight / weight;
+                    current.weight = weight;
+                } else {
+                    weight_before += current.weight;
+                    limit = self.count * Self::q_limit(weight_before / self.count);
+                    merged.push(current);
+                    current = item;
+                }
+            }
+            merged.push(current);
+            self.centroids = merged;
+        }
+
+        // 在相邻质心中心之间线性插值，两端分别用最小值和最大值
+        pub fn quantile(&mut self, q: f64) -> f64 {
+            self.flush();
+            let (first, last) = match (self.centroids.first(), self.centroids.last()) {
+                (Some(first), Some(last)) => (*first, *last),
+                _ => return f64::NAN,
+            };
+            let target = q * self.count;
+            if target <= first.weight / 2.0 {
+                let t = if first.weight > 0.0 { target / (first.weight / 2.0) } else { 0.0 };
+                return self.min + (first.mean - self.min) * t;
+            }
+            let mut center = first.weight / 2.0;
+            for pair in self.centroids.windows(2) {
+                let next_center = center + (pair[0].weight + pair[1].weight) / 2.0;
+                if target <= next_center {
+                    let t = (target - center) / (next_center - center);
+                    return pair[0].mean + (pair[1].mean - pair[0].mean) * t;
+                }
+                center = next_center;
+            }
+            let t = ((target - center) / (last.weight / 2.0)).min(1.0);
+            last.mean + (self.max - last.mean) * t
+        }
+
+        // k1 尺度下质心在 q 处覆盖的比例约为 2π·sqrt(q(1-q))/δ，插值误差不超过其一半
+        pub fn rank_error(q: f64) -> f64 {
+            100.0 * PI * (q * (1.0 - q)).sqrt() / COMPRESSION
+        }
+    }
+
+    // HyperLogLog 基数估计，相对标准误差为 1.04/sqrt(m)
+    pub struct HyperLogLog {
+        registers: Vec<u8>,
+    }
+
+    impl HyperLogLog {
+        pub fn new() -> HyperLogLog {
+            HyperLogLog { registers: vec![0; 1 << HLL_PRECISION] }
+        }
+
+        pub fn push(&mut self, value: f64) {
+            // -0.0 与 0.0 视为同一个值
+            let bits = if value == 0.0 { 0u64 } else { value.to_bits() };
+            let mut hasher = DefaultHasher::new();
+            bits.hash(&mut hasher);
+            let hash = hasher.finish();
+            let index = (hash >> (64 - HLL_PRECISION)) as usize;
+            let rest = (hash << HLL_PRECISION) | (1 << (HLL_PRECISION - 1));
+            let rank = rest.leading_zeros() as u8 + 1;
+            self.registers[index] = self.registers[index].max(rank);
+        }
+
+        pub fn estimate(&self) -> u64 {
+            let m = self.registers.len() as f64;
+            let alpha = 0.7213 / (1.0 + 1.079 / m);
+            let sum: f64 = self.registers.iter().map(|&r| 2f64.powi(-(r as i32))).sum();
+            let raw = alpha * m * m / sum;
+            let zeros = self.registers.iter().filter(|&&r| r == 0).count();
+            // 小基数时使用线性计数修正
+            let estimate = if raw <= 2.5 * m && zeros > 0 { m * (m / zeros as f64).ln() } else { raw };
+            estimate.round() as u64
+        }
+
+        pub fn relative_error(&self) -> f64 {
+            1.04 / (self.registers.len() as f64).sqrt()
+        }
+    }
+
+    // 在表头中按名称查找列，否则按从 0 开始的下标解析
+    fn column_index(column: Option<&str>, headers: Option<&csv::StringRecord>) -> Result<usize, AnalysisError> {
+        let Some(column) = column else {
+            return Ok(0);
+        };
+        if let Some(index) = headers.and_then(|headers| headers.iter().position(|h| h.trim() == column)) {
+            return Ok(index);
+        }
+        column.parse().map_err(|_| AnalysisError::new(&format!("Unknown column: {}", column)))
+    }
+
+    pub fn analyze_reader<R: io::Read>(
+        reader: R,
+        delimiter: u8,
+        has_header: bool,
+        column: Option<&str>,
+        options: &AnalysisOptions,
+    ) -> Result<StreamingAnalysis, AnalysisError> {
+        let mut reader = csv::ReaderBuilder::new()
+            .delimiter(delimiter)
+            .has_headers(has_header)
+            .flexible(true)
+            .from_reader(reader);
+        let headers = if has_header {
+            Some(reader.headers().map_err(|e| AnalysisError::new(&format!("Invalid CSV data: {}", e)))?.clone())
+        } else {
+            None
+        };
+        let index = column_index(column, headers.as_ref())?;
+
+        let mut moments = Welford::default();
+        let mut digest = TDigest::new();
+        let mut distinct = HyperLogLog::new();
+        let mut null_count = 0;
+        let mut record = csv::StringRecord::new();
+        loop {
+            match reader.read_record(&mut record) {
+                Ok(true) => {}
+                Ok(false) => break,
+                Err(e) => return Err(AnalysisError::new(&format!("Invalid CSV data: {}", e))),
+            }
+            let cell = record.get(index).unwrap_or("");
+            if table::is_null(cell) {
+                null_count += 1;
+                continue;
+            }
+            let value = table::parse_number(cell).ok_or_else(|| {
+                let line = record.position().map(|p| p.line()).unwrap_or_default();
+                AnalysisError::new(&format!("Invalid number on line {}: {}", line, cell.trim()))
+            })?;
+            moments.push(value);
+            digest.push(value);
+            distinct.push(value);
+        }
+        if moments.count == 0 {
+            return Err(AnalysisError::new("No data to analyze"));
+        }
+
+        let percentiles: Vec<ApproximatePercentile> = options
+            .percentiles
+            .iter()
+            .map(|&percentile| ApproximatePercentile {
+                percentile,
+                value: digest.quantile(percentile / 100.0),
+                rank_error: TDigest::rank_error(percentile / 100.0),
+            })
+            .collect();
+        let distinct_count = distinct.estimate();
+        let relative_error = distinct.relative_error();
+        let variance = moments.variance();
+        Ok(StreamingAnalysis {
+            total_count: moments.count,
+            null_count,
+            mean: moments.mean,
+            variance,
+            std_dev: variance.sqrt(),
+            min: moments.min,
+            max: moments.max,
+            median: digest.quantile(0.5),
+            percentiles,
+            // 估计值不可能超过总数
+            distinct_count: distinct_count.min(moments.count),
+            error_bounds: ErrorBounds {
+                percentile_rank_error: TDigest::rank_error(0.5),
+                distinct_count_relative_error: relative_error,
+                distinct_count_low: (distinct_count as f64 * (1.0 - 2.0 * relative_error)).floor().max(0.0) as u64,
+                distinct_count_high: ((distinct_count as f64 * (1.0 + 2.0 * relative_error)).ceil() as u64)
+                    .min(moments.count),
+            },
+        })
+    }
+}
+
+// 用于单元测试
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_statistics_on_unsorted_input() {
+        let result = AnalysisResult::from_values(vec![9.0, 1.0, 5.0, 3.0, 3.0, 7.0], &AnalysisOptions::default())
+            .expect("data should be analyzable");
+
+        assert_eq!(result.median, 4.0);
+        assert_eq!(result.mode, 3.0);
+        assert_eq!(result.min, 1.0);
+        assert_eq!(result.max, 9.0);
+        assert_eq!(result.q1, 3.0);
+        assert_eq!(result.q3, 6.5);
+        assert_eq!(result.histogram.iter().map(|bin| bin.count).sum::<usize>(), 6);
+    }
+
+    #[test]
+    fn test_streaming_sketches_stay_within_error_bounds() {
+        let data: String = (1..=100_000).map(|i| format!("{}\n", i % 20_000)).collect();
+        let result = streaming::analyze_reader(data.as_bytes(), b',', false, None, &AnalysisOptions::default())
+            .expect("data should be analyzable");
+
+        assert_eq!(result.total_count, 100_000);
+        assert!((result.mean - 9999.5).abs() < 1e-6);
+        assert!((result.median - 10_000.0).abs() <= 20_000.0 * result.error_bounds.percentile_rank_error / 100.0);
+        assert!(result.error_bounds.distinct_count_low <= 20_000 && 20_000 <= result.error_bounds.distinct_count_high);
+    }
+
+    #[test]
+    fn test_column_types_are_inferred_from_non_null_cells() {
+        let infer = |cells: &[&str]| table::infer_type(cells.iter().copied());
+
+        assert_eq!(infer(&["1", "2.5", "NA", "", "-3e2"]), ColumnType::Numeric);
+        assert_eq!(infer(&["yes", "No", "TRUE", "null"]), ColumnType::Boolean);
+        assert_eq!(infer(&["2024-01-01", "2024/02/03 10:00:00", "2024-03-01T00:00:00Z"]), ColumnType::Datetime);
+        assert_eq!(infer(&["1", "two", "3"]), ColumnType::Categorical);
+        assert_eq!(infer(&["", "n/a", "none"]), ColumnType::Categorical);
+    }
+
+    #[test]
+    fn test_header_and_delimiter_are_detected() {
+        let semicolons = table::parse_table("name;age\nalice;30\nbob;41\n", None, None).expect("table should parse");
+        assert!(semicolons.has_header);
+        assert_eq!(semicolons.headers, ["name", "age"]);
+        assert_eq!(semicolons.rows, [["alice", "30"], ["bob", "41"]]);
+
+        let tabs = table::parse_table("1\t2\n3\t4\n", None, None).expect("table should parse");
+        assert!(!tabs.has_header);
+        assert_eq!(tabs.headers, ["column_1", "column_2"]);
+        assert_eq!(tabs.rows.len(), 2);
+
+        // 没有任何带类型的单元格时无法区分表头，按数据处理
+        let text = table::parse_table("city,code\nparis,x\n", None, None).expect("table should parse");
+        assert!(!text.has_header);
+        assert_eq!(text.rows.len(), 2);
+
+        let forced = table::parse_table("city,code\nparis,x\n", Some(b','), Some(true)).expect("table should parse");
+        assert_eq!(forced.headers, ["city", "code"]);
+        assert_eq!(forced.rows, [["paris", "x"]]);
+    }
+
+    #[test]
+    fn test_pearson_and_spearman_correlation() {
+        let x = [1.0, 2.0, 3.0, 4.0, 5.0];
+        let squares = [1.0, 4.0, 9.0, 16.0, 25.0];
+
+        assert!((correlation::pearson(&x, &[2.0, 4.0, 6.0, 8.0, 10.0]).unwrap() - 1.0).abs() < 1e-12);
+        assert!((correlation::pearson(&x, &[5.0, 4.0, 3.0, 2.0, 1.0]).unwrap() + 1.0).abs() < 1e-12);
+        assert_eq!(correlation::pearson(&x, &[3.0; 5]), None);
+        // 单调但非线性的关系：Spearman 为 1，Pearson 小于 1
+        assert!(correlation::pearson(&x, &squares).unwrap() < 0.99);
+        assert!((correlation::spearman(&x, &squares).unwrap() - 1.0).abs() < 1e-12);
+        assert_eq!(correlation::ranks(&[10.0, 20.0, 20.0, 30.0]), [1.0, 2.5, 2.5, 4.0]);
+    }
+
+    #[test]
+    fn test_ols_coefficients_and_p_values() {
+        let columns = vec!["y".to_string(), "x".to_string()];
+        let rows: Vec<Vec<f64>> = [2.0, 4.0, 5.0, 4.0, 5.0].iter().zip(1..).map(|(&y, x)| vec![y, x as f64]).collect();
+        let result = regression::ols(columns, &rows).expect("regression should fit");
+
+        let (intercept, slope) = (&result.coefficients[0], &result.coefficients[1]);
+        assert!((intercept.estimate - 2.2).abs() < 1e-9);
+        assert!((slope.estimate - 0.6).abs() < 1e-9);
+        assert!((slope.std_error - 0.08f64.sqrt()).abs() < 1e-9);
+        assert!((slope.p_value - 0.12403).abs() < 1e-4);
+        assert!((result.r_squared - 0.6).abs() < 1e-9);
+        assert_eq!(result.degrees_of_freedom, 3);
+    }
+
+    #[test]
+    fn test_ols_perfect_fit_has_no_t_value() {
+        let columns = vec!["y".to_string(), "x".to_string()];
+        let rows: Vec<Vec<f64>> = (1..=5).map(|x| vec![1.0 + 2.0 * x as f64, x as f64]).collect();
+        let result = regression::ols(columns, &rows).expect("regression should fit");
+
+        assert_eq!(result.r_squared, 1.0);
+        assert_eq!(result.residuals.standard_error, 0.0);
+        for coefficient in &result.coefficients {
+            assert_eq!(coefficient.std_error, 0.0);
+            assert_eq!(coefficient.t_value, None);
+            assert_eq!(coefficient.p_value, 0.0);
+        }
+        let json = rocket::serde::json::serde_json::to_value(&result).expect("result should serialize");
+        assert!(json["coefficients"][1]["t_value"].is_null());
+    }
+
+    #[test]
+    fn test_outlier_methods_flag_the_extreme_value() {
+        let mut data: Vec<f64> = (0..19).map(|i| if i % 2 == 0 { 9.0 } else { 11.0 }).collect();
+        data.push(50.0);
+        let flagged = |method| {
+            let report = outliers::detect(&data, method, None, outliers::DEFAULT_SEED).expect("data should be analyzable");
+            report.outliers.iter().map(|outlier| outlier.row).collect::<Vec<_>>()
+        };
+
+        assert_eq!(flagged(OutlierMethod::ZScore), [20]);
+        assert_eq!(flagged(OutlierMethod::ModifiedZScore), [20]);
+        assert_eq!(flagged(OutlierMethod::Iqr), [20]);
+        assert_eq!(flagged(OutlierMethod::IsolationForest), [20]);
+
+        let report = outliers::detect(&[1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 100.0], OutlierMethod::Iqr, None, 0).expect("data should be analyzable");
+        assert_eq!(report.lower_fence, Some(-2.0));
+        assert_eq!(report.upper_fence, Some(10.0));
+        assert_eq!(report.outliers[0].score, 31.5);
+    }
+
+    #[test]
+    fn test_outliers_are_flagged_when_the_spread_is_zero() {
+        let data = [5.0, 5.0, 5.0, 5.0, 5.0, 5.0, 100.0];
+        for method in [OutlierMethod::ModifiedZScore, OutlierMethod::Iqr] {
+            let report = outliers::detect(&data, method, None, outliers::DEFAULT_SEED).expect("data should be analyzable");
+            assert_eq!(report.lower_fence, Some(5.0));
+            assert_eq!(report.upper_fence, Some(5.0));
+            assert_eq!(report.outlier_count, 1);
+            assert_eq!(report.outliers[0].row, 7);
+            assert_eq!(report.outliers[0].score, f64::INFINITY);
+        }
+
+        let report = outliers::detect(&[5.0; 4], OutlierMethod::ZScore, None, outliers::DEFAULT_SEED).expect("data should be analyzable");
+        assert_eq!(report.outlier_count, 0);
+    }
+
+    #[test]
+    fn test_cache_starts_empty_on_a_corrupt_file_and_writes_on_flush() {
+        let path = std::env::temp_dir().join(format!("analyzer-cache-{}.json", uuid::Uuid::new_v4().simple()));
+        std::fs::write(&path, b"{not json").unwrap();
+        let config = cache::CacheConfig { path: path.clone(), ..cache::CacheConfig::default() };
+        let cache = cache::ResultCache::load(config).expect("a corrupt cache file should not fail startup");
+        assert!(cache.list().is_empty());
+
+        let options = AnalysisOptions::default();
+        let result = AnalysisResult::from_values(vec![1.0, 2.0, 3.0], &options).unwrap();
+        cache.insert(cache::CacheEntry::new("key".to_string(), "hash".to_string(), "test".to_string(), options, result));
+        assert_eq!(std::fs::read(&path).unwrap(), b"{not json");
+
+        cache.flush().unwrap();
+        let config = cache::CacheConfig { path: path.clone(), ..cache::CacheConfig::default() };
+        let reloaded = cache::ResultCache::load(config).unwrap();
+        assert_eq!(reloaded.list().len(), 1);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    fn hourly_options(fill: FillStrategy, window: usize) -> timeseries::TimeSeriesOptions {
+        timeseries::TimeSeriesOptions { interval: Some(3600), fill, window, alpha: None, period: None, lags: Some(vec![1]) }
+    }
+
+    #[test]
+    fn test_resampling_and_fill_strategies() {
+        let text = "time,value\n2024-01-01 00:00:00,1\n2024-01-01 00:30:00,3\n2024-01-01 01:00:00,4\n2024-01-01 03:00:00,10\n";
+        let table = table::parse_table(text, None, None).expect("table should parse");
+
+        for (fill, expected) in [
+            (FillStrategy::Zero, 0.0),
+            (FillStrategy::Forward, 4.0),
+            (FillStrategy::Backward, 10.0),
+            (FillStrategy::Linear, 7.0),
+            (FillStrategy::Mean, 16.0 / 3.0),
+        ] {
+            let result = timeseries::analyze(&table, None, None, &hourly_options(fill, 1)).expect("series should analyze");
+            let values: Vec<f64> = result.points.iter().map(|point| point.value).collect();
+            // 同一小时内的两个值取平均，02:00 没有数据
+            assert_eq!(values[..2], [2.0, 4.0]);
+            assert!((values[2] - expected).abs() < 1e-12, "{:?} filled {}", fill, values[2]);
+            assert_eq!(values[3], 10.0);
+            assert!(!result.points[2].observed);
+            assert_eq!(result.points[2].timestamp, "2024-01-01T02:00:00");
+            assert_eq!((result.observed_count, result.filled_count), (3, 1));
+        }
+
+        assert!(timeseries::analyze(&table, None, None, &hourly_options(FillStrategy::Zero, 5)).is_err());
+        assert!(timeseries::parse_interval("99999999999999999w").is_err());
+        assert!(timeseries::parse_interval("2000000w").is_err());
+        assert_eq!(timeseries::parse_interval("15m").unwrap(), 900);
+    }
+
+    #[test]
+    fn test_moving_averages() {
+        assert_eq!(timeseries::sma(&[1.0, 2.0, 3.0, 4.0], 2), [None, Some(1.5), Some(2.5), Some(3.5)]);
+        let wma = timeseries::wma(&[1.0, 2.0, 3.0, 6.0], 3);
+        assert_eq!(wma[..2], [None, None]);
+        assert!((wma[2].unwrap() - 14.0 / 6.0).abs() < 1e-12);
+        assert!((wma[3].unwrap() - 26.0 / 6.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_decomposition_separates_trend_and_season() {
+        let season = |i: usize| 10.0 * (2.0 * std::f64::consts::PI * i as f64 / 12.0).sin();
+        let values: Vec<f64> = (0..96).map(|i| 0.5 * i as f64 + season(i)).collect();
+        let decomposition = timeseries::decompose(&values, 12).expect("series should decompose");
+
+        assert!(decomposition.seasonal_strength > 0.9);
+        assert!(decomposition.trend_strength > 0.9);
+        for i in 24..72 {
+            assert!((decomposition.seasonal[i] - season(i)).abs() < 1.5, "seasonal[{}] = {}", i, decomposition.seasonal[i]);
+            assert!((decomposition.trend[i] - 0.5 * i as f64).abs() < 1.5, "trend[{}] = {}", i, decomposition.trend[i]);
+        }
+
+        assert!(timeseries::decompose(&values, 1).is_err());
+        assert!(timeseries::decompose(&values, 49).is_err());
+        assert!(timeseries::decompose(&[0.0; 10_000], 2_000).is_err());
+    }
+
+    fn sales_table() -> table::Table {
+        let text = "region,product,sales,qty\n\
+                    east,a,10,1\n\
+                    east,a,30,2\n\
+                    east,b,5,\n\
+                    west,a,7,3\n\
+                    west,b,100,4\n\
+                    west,b,,5\n\
+                    north,c,1,1\n";
+        table::parse_table(text, Some(b','), Some(true)).expect("table should parse")
+    }
+
+    #[test]
+    fn test_group_by_multiple_keys_and_percentiles() {
+        let table = sales_table();
+        let keys = ["region".to_string(), "product".to_string()];
+        let aggregates =
+            aggregate::Aggregate::parse_list("sum(sales), count(*), count(sales), median(sales), p90(qty)").unwrap();
+        let result = aggregate::group_by(&table, &keys, &aggregates).expect("grouping should succeed");
+
+        assert_eq!(result.aggregates, ["sum(sales)", "count(*)", "count(sales)", "p50(sales)", "p90(qty)"]);
+        let keys: Vec<_> = result.groups.iter().map(|g| g.key.join("/")).collect();
+        assert_eq!(keys, ["east/a", "east/b", "north/c", "west/a", "west/b"]);
+        assert_eq!(result.groups[0].values[..4], [Some(40.0), Some(2.0), Some(2.0), Some(20.0)]);
+        assert!((result.groups[0].values[4].unwrap() - 1.9).abs() < 1e-12);
+        // 没有数值的分组，百分位为 null
+        assert_eq!(result.groups[1].values[4], None);
+        // 空单元格计入 count(*)，不计入 count(sales)
+        assert_eq!(result.groups[4].values[..4], [Some(100.0), Some(2.0), Some(1.0), Some(100.0)]);
+        assert!((result.groups[4].values[4].unwrap() - 4.9).abs() < 1e-12);
+
+        assert!(aggregate::group_by(&table, &[], &aggregates).is_err());
+        let text_sum = aggregate::Aggregate::parse_list("sum(product)").unwrap();
+        assert!(aggregate::group_by(&table, &["region".to_string()], &text_sum).is_err());
+        assert!(aggregate::Aggregate::parse("p101(sales)").is_err());
+        assert!(aggregate::Aggregate::parse("sum(*)").is_err());
+    }
+
+    #[test]
+    fn test_pivot_fills_empty_cells() {
+        let table = sales_table();
+        let rows = ["region".to_string()];
+        let columns = ["product".to_string()];
+        let sum = aggregate::Aggregate::parse("sum(sales)").unwrap();
+        let pivot = aggregate::pivot(&table, &rows, &columns, &sum).expect("pivot should succeed");
+
+        assert_eq!(pivot.columns, [["a"], ["b"], ["c"]]);
+        assert_eq!(pivot.rows[0].key, ["east"]);
+        assert_eq!(pivot.rows[0].values, [Some(40.0), Some(5.0), None]);
+        assert_eq!(pivot.rows[1].values, [None, None, Some(1.0)]);
+
+        // count 的空单元格为 0 而不是 null
+        let count = aggregate::Aggregate::parse("count(*)").unwrap();
+        let counts = aggregate::pivot(&table, &rows, &columns, &count).expect("pivot should succeed");
+        assert_eq!(counts.rows[1].values, [Some(0.0), Some(0.0), Some(1.0)]);
+
+        assert!(aggregate::pivot(&table, &rows, &[], &sum).is_err());
+    }
+
+    #[test]
+    fn test_group_by_and_pivot_csv_layout() {
+        let table = sales_table();
+        let aggregates = aggregate::Aggregate::parse_list("sum(sales),count(*)").unwrap();
+        let grouped = aggregate::group_by(&table, &["region".to_string()], &aggregates).unwrap();
+        assert_eq!(
+            aggregate::group_by_csv(&grouped).unwrap(),
+            "region,sum(sales),count(*)\neast,45,3\nnorth,1,1\nwest,107,3\n"
+        );
+
+        let sum = aggregate::Aggregate::parse("sum(sales)").unwrap();
+        let pivot = aggregate::pivot(&table, &["region".to_string()], &["product".to_string()], &sum).unwrap();
+        assert_eq!(aggregate::pivot_csv(&pivot).unwrap(), "region,a,b,c\neast,40,5,\nnorth,,,1\nwest,7,100,\n");
+
+        // 多个列键以 " | " 连接成列名
+        let columns = ["product".to_string(), "qty".to_string()];
+        let wide = aggregate::pivot(&table, &["region".to_string()], &columns, &sum).unwrap();
+        let csv = aggregate::pivot_csv(&wide).unwrap();
+        assert_eq!(csv.lines().next().unwrap(), "region,a | 1,a | 2,a | 3,b | ,b | 4,b | 5,c | 1");
     }
 }
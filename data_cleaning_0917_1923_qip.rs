@@ -1,12 +1,167 @@
-use rocket::get;
-use rocket::serde::json::Json;
+use rocket::{get, post, put, State};
+use rocket::http::Status;
+use rocket::response::status;
+use rocket::serde::json::{serde_json, Json};
 use rocket::serde::{Serialize, Deserialize};
+use regex::{Regex, RegexBuilder};
+use std::collections::HashMap;
+use std::io;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use unicode_normalization::UnicodeNormalization;
+
+// 清洗步骤，以 {"step": "...", ...} 的形式在请求或配置方案中给出
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "step", rename_all = "snake_case")]
+enum CleaningStep {
+    // 去除首尾空白
+    Trim,
+    // 连续空白（含换行）合并为一个空格
+    CollapseWhitespace,
+    // Unicode 规范化
+    Nfc,
+    Nfkc,
+    // 全角 ASCII 字符和全角空格转为半角
+    HalfWidth,
+    // 删除控制字符和零宽字符，保留换行与制表符
+    StripControl,
+    // Unicode 完全大小写折叠（CaseFolding.txt 的 C+F 映射，如 ß → ss）
+    CaseFold,
+    // 正则替换，replacement 中可以用 $1、${name} 引用分组
+    RegexReplace { pattern: String, replacement: String },
+}
+
+impl CleaningStep {
+    fn name(&self) -> &'static str {
+        match self {
+            CleaningStep::Trim => "trim",
+            CleaningStep::CollapseWhitespace => "collapse_whitespace",
+            CleaningStep::Nfc => "nfc",
+            CleaningStep::Nfkc => "nfkc",
+            CleaningStep::HalfWidth => "half_width",
+            CleaningStep::StripControl => "strip_control",
+            CleaningStep::CaseFold => "case_fold",
+            CleaningStep::RegexReplace { .. } => "regex_replace",
+        }
+    }
+}
+
+// 未指定步骤和方案时使用的默认清洗流程
+fn default_steps() -> Vec<CleaningStep> {
+    vec![CleaningStep::StripControl, CleaningStep::CollapseWhitespace, CleaningStep::Trim]
+}
+
+// 编译后的清洗流程，正则替换步骤附带编译好的正则，只编译一次
+struct Pipeline {
+    steps: Vec<(CleaningStep, Option<Regex>)>,
+}
+
+impl Pipeline {
+    fn steps(&self) -> Vec<CleaningStep> {
+        self.steps.iter().map(|(step, _)| step.clone()).collect()
+    }
+
+    // 校验并编译所有步骤，正则表达式大小受限以防止过大的模式
+    fn compile(steps: &[CleaningStep]) -> Result<Pipeline, String> {
+        let steps = steps
+            .iter()
+            .enumerate()
+            .map(|(index, step)| {
+                let regex = match step {
+                    CleaningStep::RegexReplace { pattern, .. } => Some(
+                        RegexBuilder::new(pattern)
+                            .size_limit(1 << 20)
+                            .build()
+                            .map_err(|e| format!("Step {} has an invalid pattern: {}", index, e))?,
+                    ),
+                    _ => None,
+                };
+                Ok((step.clone(), regex))
+            })
+            .collect::<Result<_, String>>()?;
+        Ok(Pipeline { steps })
+    }
+
+    // 依次执行各步骤，返回结果以及改变了文本的步骤
+    fn run(&self, data: &str) -> (String, Vec<AppliedStep>) {
+        let mut current = data.to_string();
+        let mut changed = Vec::new();
+        for (index, (step, regex)) in self.steps.iter().enumerate() {
+            let next = apply_step(step, regex.as_ref(), &current);
+            if next != current {
+                changed.push(AppliedStep { index, step: step.name().to_string() });
+                current = next;
+            }
+        }
+        (current, changed)
+    }
+}
+
+fn apply_step(step: &CleaningStep, regex: Option<&Regex>, data: &str) -> String {
+    match step {
+        CleaningStep::Trim => data.trim().to_string(),
+        CleaningStep::CollapseWhitespace => {
+            let mut collapsed = String::with_capacity(data.len());
+            let mut in_space = false;
+            for c in data.chars() {
+                if c.is_whitespace() {
+                    if !in_space {
+                        collapsed.push(' ');
+                    }
+                    in_space = true;
+                } else {
+                    collapsed.push(c);
+                    in_space = false;
+                }
+            }
+            collapsed
+        }
+        CleaningStep::Nfc => data.nfc().collect(),
+        CleaningStep::Nfkc => data.nfkc().collect(),
+        CleaningStep::HalfWidth => data.chars().map(to_half_width).collect(),
+        CleaningStep::StripControl => data
+            .chars()
+            .filter(|&c| c == '\n' || c == '\t' || !(c.is_control() || is_zero_width(c)))
+            .collect(),
+        CleaningStep::CaseFold => caseless::default_case_fold_str(data),
+        CleaningStep::RegexReplace { replacement, .. } => {
+            let regex = regex.expect("regex steps are compiled");
+            regex.replace_all(data, replacement.as_str()).into_owned()
+        }
+    }
+}
+
+// 全角 ASCII（U+FF01 到 U+FF5E）与 ASCII 相差 0xFEE0，全角空格为 U+3000
+fn to_half_width(c: char) -> char {
+    match c {
+        '\u{3000}' => ' ',
+        '\u{FF01}'..='\u{FF5E}' => char::from_u32(c as u32 - 0xFEE0).unwrap_or(c),
+        _ => c,
+    }
+}
+
+fn is_zero_width(c: char) -> bool {
+    matches!(c, '\u{200B}' | '\u{200C}' | '\u{200D}' | '\u{2060}' | '\u{FEFF}')
+}
 
 // 定义数据清洗请求结构体
 #[derive(Serialize, Deserialize, Debug)]
 struct CleanDataRequest {
     // 待清洗的数据
     data: String,
+    // 清洗步骤，与 profile 二选一
+    #[serde(default)]
+    steps: Option<Vec<CleaningStep>>,
+    // 已保存的清洗方案名称
+    #[serde(default)]
+    profile: Option<String>,
+}
+
+// 改变了文本的步骤及其在流程中的位置
+#[derive(Serialize, Deserialize, Debug)]
+struct AppliedStep {
+    index: usize,
+    step: String,
 }
 
 // 定义数据清洗后的结构体
@@ -14,28 +169,244 @@ struct CleanDataRequest {
 struct CleanDataResponse {
     // 清洗后的数据
     cleaned_data: String,
+    // 实际改变了数据的步骤
+    changed_steps: Vec<AppliedStep>,
+}
+
+// 已保存的清洗方案，持久化为一个 JSON 文件；内存中保存编译好的流程，请求时不再重复编译
+struct ProfileStore {
+    path: PathBuf,
+    profiles: Mutex<Profiles>,
+}
+
+struct Profiles {
+    compiled: HashMap<String, Arc<Pipeline>>,
+    // 加载时无法解析或编译的方案保留原始定义，保存时原样写回，不会因为一次保存而丢失
+    invalid: HashMap<String, serde_json::Value>,
+}
+
+impl ProfileStore {
+    // 文件损坏时另存为 .corrupt 并从空方案开始，无法编译的方案跳过，都不影响启动
+    fn load(path: PathBuf) -> io::Result<ProfileStore> {
+        let saved: HashMap<String, serde_json::Value> = match std::fs::read(&path) {
+            Ok(bytes) => serde_json::from_slice(&bytes).unwrap_or_else(|e| {
+                let backup = path.with_extension("json.corrupt");
+                eprintln!("Ignoring unreadable cleaning profiles {} ({}); moved to {}", path.display(), e, backup.display());
+                if let Err(e) = std::fs::rename(&path, &backup) {
+                    eprintln!("Failed to move {}: {}", path.display(), e);
+                }
+                HashMap::new()
+            }),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => HashMap::new(),
+            Err(e) => return Err(e),
+        };
+        let mut profiles = Profiles { compiled: HashMap::new(), invalid: HashMap::new() };
+        for (name, definition) in saved {
+            let compiled = serde_json::from_value::<Vec<CleaningStep>>(definition.clone())
+                .map_err(|e| e.to_string())
+                .and_then(|steps| Pipeline::compile(&steps));
+            match compiled {
+                Ok(pipeline) => {
+                    profiles.compiled.insert(name, Arc::new(pipeline));
+                }
+                Err(e) => {
+                    eprintln!("Skipping cleaning profile {}: {}", name, e);
+                    profiles.invalid.insert(name, definition);
+                }
+            }
+        }
+        Ok(ProfileStore { path, profiles: Mutex::new(profiles) })
+    }
+
+    fn get(&self, name: &str) -> Option<Arc<Pipeline>> {
+        self.profiles.lock().unwrap().compiled.get(name).cloned()
+    }
+
+    fn list(&self) -> HashMap<String, Vec<CleaningStep>> {
+        let profiles = self.profiles.lock().unwrap();
+        profiles.compiled.iter().map(|(name, pipeline)| (name.clone(), pipeline.steps())).collect()
+    }
+
+    // 先写临时文件再重命名，写入成功后才更新内存中的方案；同名的无效方案被新方案取代
+    fn save(&self, name: &str, pipeline: Pipeline) -> io::Result<()> {
+        let mut profiles = self.profiles.lock().unwrap();
+        let to_value =
+            |steps: Vec<CleaningStep>| serde_json::to_value(steps).map_err(|e| io::Error::new(io::ErrorKind::Other, e));
+        let mut saved: HashMap<&str, serde_json::Value> =
+            profiles.invalid.iter().map(|(name, definition)| (name.as_str(), definition.clone())).collect();
+        for (name, pipeline) in &profiles.compiled {
+            saved.insert(name, to_value(pipeline.steps())?);
+        }
+        saved.insert(name, to_value(pipeline.steps())?);
+        let json = serde_json::to_vec_pretty(&saved).map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+        let tmp = self.path.with_extension("json.tmp");
+        std::fs::write(&tmp, json)?;
+        std::fs::rename(&tmp, &self.path)?;
+        profiles.invalid.remove(name);
+        profiles.compiled.insert(name.to_string(), Arc::new(pipeline));
+        Ok(())
+    }
 }
 
-// 数据清洗函数
-fn clean_data(data: &str) -> String {
-    // 这里可以添加实际的数据清洗逻辑，例如去除特殊字符、空格等
-    // 仅为示例，此处仅返回原始数据
-    data.to_string()
+// 数据清洗函数：按请求中的步骤、保存的方案或默认流程清洗数据
+fn clean_data(request: &CleanDataRequest, profiles: &ProfileStore) -> Result<CleanDataResponse, status::Custom<String>> {
+    let compile = |steps: &[CleaningStep]| {
+        Pipeline::compile(steps).map(Arc::new).map_err(|e| status::Custom(Status::BadRequest, e))
+    };
+    let pipeline = match (&request.steps, &request.profile) {
+        (Some(_), Some(_)) => {
+            return Err(status::Custom(Status::BadRequest, "Give either steps or profile, not both".to_string()));
+        }
+        (Some(steps), None) => compile(steps)?,
+        (None, Some(name)) => profiles
+            .get(name)
+            .ok_or_else(|| status::Custom(Status::NotFound, format!("Unknown cleaning profile: {}", name)))?,
+        (None, None) => compile(&default_steps())?,
+    };
+    let (cleaned_data, changed_steps) = pipeline.run(&request.data);
+    Ok(CleanDataResponse { cleaned_data, changed_steps })
 }
 
-// 数据清洗接口
-#[get("/clean_data")]
-fn clean_data_api(request: Json<CleanDataRequest>) -> Json<CleanDataResponse> {
+// 数据清洗接口，保留最初的 GET 形式以兼容已有的调用方
+#[get("/clean_data", data = "<request>")]
+fn clean_data_get(
+    request: Json<CleanDataRequest>,
+    profiles: &State<ProfileStore>,
+) -> Result<Json<CleanDataResponse>, status::Custom<String>> {
+    clean_data_api(request, profiles)
+}
+
+#[post("/clean_data", format = "json", data = "<request>")]
+fn clean_data_api(
+    request: Json<CleanDataRequest>,
+    profiles: &State<ProfileStore>,
+) -> Result<Json<CleanDataResponse>, status::Custom<String>> {
     // 调用数据清洗函数
-    let cleaned_data = clean_data(&request.data);
-    
+    let response = clean_data(&request, profiles)?;
+
     // 返回清洗后的数据
-    Json(CleanDataResponse { cleaned_data })
+    Ok(Json(response))
+}
+
+// 保存或覆盖一个清洗方案，保存前会先校验步骤
+#[put("/clean_data/profiles/<name>", format = "json", data = "<steps>")]
+fn save_profile(
+    name: &str,
+    steps: Json<Vec<CleaningStep>>,
+    profiles: &State<ProfileStore>,
+) -> Result<Status, status::Custom<String>> {
+    let pipeline = Pipeline::compile(&steps).map_err(|e| status::Custom(Status::BadRequest, e))?;
+    profiles
+        .save(name, pipeline)
+        .map_err(|e| status::Custom(Status::InternalServerError, format!("Failed to save the profile: {}", e)))?;
+    Ok(Status::NoContent)
+}
+
+#[get("/clean_data/profiles")]
+fn list_profiles(profiles: &State<ProfileStore>) -> Json<HashMap<String, Vec<CleaningStep>>> {
+    Json(profiles.list())
 }
 
 // 启动ROCKET服务器
 #[launch]
 fn rocket() -> _ {
-    rocket::build()
-        .mount("/", routes![clean_data_api])
+    let rocket = rocket::build();
+    let profile_path: PathBuf = rocket
+        .figment()
+        .extract_inner("cleaning_profiles")
+        .unwrap_or_else(|_| std::env::temp_dir().join("cleaning-profiles.json"));
+    let profiles = ProfileStore::load(profile_path).expect("Failed to load cleaning profiles");
+    rocket
+        .manage(profiles)
+        .mount("/", routes![clean_data_get, clean_data_api, save_profile, list_profiles])
+}
+
+// 用于单元测试
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(steps: Vec<CleaningStep>, data: &str) -> (String, Vec<AppliedStep>) {
+        Pipeline::compile(&steps).expect("steps should compile").run(data)
+    }
+
+    fn clean(step: CleaningStep, data: &str) -> String {
+        run(vec![step], data).0
+    }
+
+    #[test]
+    fn test_each_step() {
+        assert_eq!(clean(CleaningStep::Trim, "  a b \n"), "a b");
+        assert_eq!(clean(CleaningStep::CollapseWhitespace, "a \t\n b  c"), "a b c");
+        assert_eq!(clean(CleaningStep::Nfc, "e\u{301}"), "\u{e9}");
+        assert_eq!(clean(CleaningStep::Nfkc, "\u{FB01}\u{2460}"), "fi1");
+        assert_eq!(clean(CleaningStep::HalfWidth, "\u{FF21}\u{FF22}\u{3000}\u{FF11}"), "AB 1");
+        assert_eq!(clean(CleaningStep::StripControl, "a\u{200B}b\u{7}\tc\n"), "ab\tc\n");
+        assert_eq!(clean(CleaningStep::CaseFold, "Stra\u{DF}e \u{3A3}\u{39F}\u{3A3}"), "strasse \u{3C3}\u{3BF}\u{3C3}");
+        let replace = CleaningStep::RegexReplace { pattern: r"(\d+)-(\d+)".to_string(), replacement: "$2/$1".to_string() };
+        assert_eq!(clean(replace, "call 555-1234"), "call 1234/555");
+    }
+
+    #[test]
+    fn test_changed_steps_lists_only_steps_that_changed_the_text() {
+        let steps = vec![CleaningStep::Trim, CleaningStep::CaseFold, CleaningStep::Trim, CleaningStep::HalfWidth];
+        let (cleaned, changed) = run(steps, " ABC ");
+
+        assert_eq!(cleaned, "abc");
+        let changed: Vec<(usize, &str)> = changed.iter().map(|applied| (applied.index, applied.step.as_str())).collect();
+        assert_eq!(changed, [(0, "trim"), (1, "case_fold")]);
+    }
+
+    #[test]
+    fn test_invalid_patterns_are_rejected_with_the_step_index() {
+        let steps = vec![CleaningStep::Trim, CleaningStep::RegexReplace { pattern: "(".to_string(), replacement: String::new() }];
+        let error = Pipeline::compile(&steps).err().expect("pattern should not compile");
+        assert!(error.starts_with("Step 1 has an invalid pattern"), "{}", error);
+    }
+
+    #[test]
+    fn test_profiles_survive_a_reload_and_a_corrupt_file_does_not_stop_startup() {
+        let dir = std::env::temp_dir().join(format!("cleaning-profiles-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("profiles.json");
+        std::fs::write(&path, b"{broken").unwrap();
+
+        let store = ProfileStore::load(path.clone()).expect("a corrupt file should not fail startup");
+        assert!(store.list().is_empty());
+        assert!(path.with_extension("json.corrupt").exists());
+
+        store.save("lower", Pipeline::compile(&[CleaningStep::CaseFold]).unwrap()).unwrap();
+        let reloaded = ProfileStore::load(path.clone()).unwrap();
+        assert_eq!(reloaded.get("lower").expect("profile should be saved").run("ABC").0, "abc");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_invalid_profiles_are_kept_when_saving() {
+        let dir = std::env::temp_dir().join(format!("cleaning-invalid-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("profiles.json");
+        let saved = r#"{
+            "bad_pattern": [{"step": "regex_replace", "pattern": "(", "replacement": ""}],
+            "unknown_step": [{"step": "shout"}],
+            "trim": [{"step": "trim"}]
+        }"#;
+        std::fs::write(&path, saved).unwrap();
+
+        let store = ProfileStore::load(path.clone()).unwrap();
+        assert_eq!(store.list().len(), 1);
+        store.save("lower", Pipeline::compile(&[CleaningStep::CaseFold]).unwrap()).unwrap();
+
+        let written: HashMap<String, serde_json::Value> = serde_json::from_slice(&std::fs::read(&path).unwrap()).unwrap();
+        assert_eq!(written["bad_pattern"][0]["pattern"], "(");
+        assert_eq!(written["unknown_step"][0]["step"], "shout");
+        assert!(written.contains_key("trim") && written.contains_key("lower"));
+
+        // 用同名的有效方案覆盖后，无效的定义不再写回
+        store.save("bad_pattern", Pipeline::compile(&[CleaningStep::Trim]).unwrap()).unwrap();
+        let reloaded = ProfileStore::load(path.clone()).unwrap();
+        assert_eq!(reloaded.list().len(), 3);
+        assert!(reloaded.get("unknown_step").is_none());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
 }
@@ -5,6 +5,11 @@ use std::fs::File;
 use std::io::{self, BufRead, BufReader};
 use std::path::PathBuf;
 
+#[path = "response_compression_fairing.rs"]
+mod response_compression;
+
+use response_compression::ResponseCompression;
+
 // 定义日志文件解析结果的结构体
 #[derive(Debug, Serialize, Deserialize, PartialEq)]
 struct LogEntry {
@@ -42,5 +47,7 @@ fn log_parser(file_path: PathBuf) -> io::Result<Json<Vec<LogEntry>>> {
 
 #[launch]
 fn rocket() -> _ {
-    rocket::build().mount("/", routes![log_parser])
+    rocket::build()
+        .attach(ResponseCompression::default())
+        .mount("/", routes![log_parser])
 }
\ No newline at end of file
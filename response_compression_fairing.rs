@@ -0,0 +1,247 @@
+// Codec selection and a response-compression fairing shared by the Rocket services.
+// Include it with `#[path = "response_compression_fairing.rs"] mod response_compression;`
+// and attach `ResponseCompression::default()` to compress JSON and text responses.
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::http::{ContentType, Header};
+use rocket::request::{self, FromRequest, Request};
+use rocket::Response;
+use flate2::write::{DeflateEncoder, GzEncoder, ZlibEncoder};
+use flate2::Compression;
+use std::io;
+use std::io::prelude::*;
+
+// The codecs supported by the service
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Codec {
+    Gzip,
+    Zlib,
+    Deflate,
+    Zstd,
+    Brotli,
+}
+
+impl Codec {
+    // Every supported codec, in the order used when a client has no preference
+    pub const ALL: [Codec; 5] = [Codec::Gzip, Codec::Zlib, Codec::Deflate, Codec::Zstd, Codec::Brotli];
+
+    // Parse a codec name as used in query parameters and `Accept-Encoding` tokens.
+    // Following HTTP, `deflate` means the zlib-wrapped stream; raw deflate is `raw-deflate`.
+    pub fn from_name(name: &str) -> Option<Codec> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "gzip" | "x-gzip" => Some(Codec::Gzip),
+            "zlib" | "deflate" => Some(Codec::Zlib),
+            "raw-deflate" | "deflate-raw" => Some(Codec::Deflate),
+            "zstd" => Some(Codec::Zstd),
+            "br" | "brotli" => Some(Codec::Brotli),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Codec::Gzip => "gzip",
+            Codec::Zlib => "zlib",
+            Codec::Deflate => "raw-deflate",
+            Codec::Zstd => "zstd",
+            Codec::Brotli => "br",
+        }
+    }
+
+    // The `Content-Encoding` token for the codec; raw deflate has none in HTTP
+    pub fn content_encoding(self) -> Option<&'static str> {
+        match self {
+            Codec::Gzip => Some("gzip"),
+            Codec::Zlib => Some("deflate"),
+            Codec::Deflate => None,
+            Codec::Zstd => Some("zstd"),
+            Codec::Brotli => Some("br"),
+        }
+    }
+
+    // The valid compression levels and the level used when none is given
+    pub fn levels(self) -> (i32, i32, i32) {
+        match self {
+            Codec::Gzip | Codec::Zlib | Codec::Deflate => (0, 9, 6),
+            Codec::Zstd => (1, 22, 3),
+            Codec::Brotli => (0, 11, 6),
+        }
+    }
+
+    // Resolve the requested level, rejecting values outside the codec's range
+    pub fn level(self, requested: Option<i32>) -> Option<i32> {
+        let (min, max, default) = self.levels();
+        match requested {
+            None => Some(default),
+            Some(level) if (min..=max).contains(&level) => Some(level),
+            Some(_) => None,
+        }
+    }
+
+    // Guess the codec of a compressed buffer from its magic bytes.
+    // Raw deflate and brotli carry no signature, so they must be named explicitly.
+    pub fn detect(input: &[u8]) -> Option<Codec> {
+        match input {
+            [0x1f, 0x8b, ..] => Some(Codec::Gzip),
+            [0x28, 0xb5, 0x2f, 0xfd, ..] => Some(Codec::Zstd),
+            [cmf, flg, ..] if cmf & 0x0f == 8 && (u16::from(*cmf) << 8 | u16::from(*flg)) % 31 == 0 => {
+                Some(Codec::Zlib)
+            }
+            _ => None,
+        }
+    }
+}
+
+// The codecs a client accepts, taken from its `Accept-Encoding` header and ordered by q-value
+pub struct AcceptedCodecs(Vec<Codec>);
+
+impl AcceptedCodecs {
+    pub fn parse(header: &str) -> AcceptedCodecs {
+        let mut weighted: Vec<(Codec, f32)> = header
+            .split(',')
+            .filter_map(|item| {
+                let mut parts = item.split(';');
+                let codec = Codec::from_name(parts.next()?)?;
+                let q = parts
+                    .filter_map(|p| p.trim().strip_prefix("q="))
+                    .find_map(|q| q.trim().parse::<f32>().ok())
+                    .unwrap_or(1.0);
+                Some((codec, q))
+            })
+            .filter(|&(_, q)| q > 0.0)
+            .collect();
+        // A stable sort keeps the client's order for codecs with equal weight
+        weighted.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+        AcceptedCodecs(weighted.into_iter().map(|(codec, _)| codec).collect())
+    }
+
+    pub fn preferred(&self) -> Option<Codec> {
+        self.0.first().copied()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = Codec> + '_ {
+        self.0.iter().copied()
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for AcceptedCodecs {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        let header = request.headers().get_one("Accept-Encoding").unwrap_or_default();
+        request::Outcome::Success(AcceptedCodecs::parse(header))
+    }
+}
+
+// Compress the input content with the given codec and level
+pub fn compress_content(input: &[u8], codec: Codec, level: i32) -> io::Result<Vec<u8>> {
+    match codec {
+        Codec::Gzip => {
+            let mut encoder = GzEncoder::new(Vec::new(), Compression::new(level as u32));
+            encoder.write_all(input)?;
+            encoder.finish()
+        }
+        Codec::Zlib => {
+            let mut encoder = ZlibEncoder::new(Vec::new(), Compression::new(level as u32));
+            encoder.write_all(input)?;
+            encoder.finish()
+        }
+        Codec::Deflate => {
+            let mut encoder = DeflateEncoder::new(Vec::new(), Compression::new(level as u32));
+            encoder.write_all(input)?;
+            encoder.finish()
+        }
+        Codec::Zstd => zstd::stream::encode_all(input, level),
+        Codec::Brotli => {
+            let mut output = Vec::new();
+            {
+                let mut encoder = brotli::CompressorWriter::new(&mut output, 4096, level as u32, 22);
+                encoder.write_all(input)?;
+            }
+            Ok(output)
+        }
+    }
+}
+
+// Compresses response bodies for clients that advertise support in `Accept-Encoding`.
+// Only sized bodies of an allowed content type and at least `min_size` bytes are touched,
+// so streamed responses and already encoded payloads pass through unchanged.
+pub struct ResponseCompression {
+    min_size: usize,
+    content_types: Vec<ContentType>,
+}
+
+impl Default for ResponseCompression {
+    fn default() -> Self {
+        ResponseCompression {
+            min_size: 1024,
+            content_types: vec![
+                ContentType::JSON,
+                ContentType::new("text", "*"),
+                ContentType::JavaScript,
+                ContentType::XML,
+                ContentType::SVG,
+            ],
+        }
+    }
+}
+
+impl ResponseCompression {
+    // Bodies smaller than this are sent as they are
+    pub fn min_size(mut self, min_size: usize) -> Self {
+        self.min_size = min_size;
+        self
+    }
+
+    // Replace the allowlist; a `*` subtype matches every subtype of the top-level type
+    pub fn content_types(mut self, content_types: Vec<ContentType>) -> Self {
+        self.content_types = content_types;
+        self
+    }
+
+    fn allows(&self, content_type: &ContentType) -> bool {
+        self.content_types.iter().any(|allowed| {
+            allowed.top() == content_type.top() && (allowed.sub() == "*" || allowed.sub() == content_type.sub())
+        })
+    }
+}
+
+#[rocket::async_trait]
+impl Fairing for ResponseCompression {
+    fn info(&self) -> Info {
+        Info { name: "Response compression", kind: Kind::Response }
+    }
+
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+        if response.headers().contains("Content-Encoding") {
+            return;
+        }
+        if !response.content_type().map_or(false, |ct| self.allows(&ct)) {
+            return;
+        }
+        // Whether this response gets compressed depends on Accept-Encoding, so shared caches
+        // must key on it even when this particular client gets the identity encoding
+        response.adjoin_raw_header("Vary", "Accept-Encoding");
+        match response.body().preset_size() {
+            Some(size) if size >= self.min_size => {}
+            _ => return,
+        }
+
+        let accepted = AcceptedCodecs::parse(request.headers().get_one("Accept-Encoding").unwrap_or_default());
+        let Some(codec) = accepted.iter().find(|codec| codec.content_encoding().is_some()) else {
+            return;
+        };
+        let Ok(body) = response.body_mut().to_bytes().await else {
+            return;
+        };
+        let level = codec.level(None).unwrap_or_default();
+        let body = match compress_content(&body, codec, level) {
+            Ok(compressed) => {
+                response.set_header(Header::new("Content-Encoding", codec.content_encoding().unwrap_or_default()));
+                compressed
+            }
+            Err(_) => body,
+        };
+        response.set_sized_body(body.len(), io::Cursor::new(body));
+    }
+}